@@ -1,3 +1,24 @@
 fn main() {
+    export_btleplug_version();
     tauri_build::build()
 }
+
+/// Makes the btleplug version resolved in Cargo.lock available as
+/// `BTLEPLUG_VERSION`, for the adapter-info request.
+fn export_btleplug_version() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let version = lock
+        .split("[[package]]")
+        .find(|package| package.contains("name = \"btleplug\""))
+        .and_then(|package| {
+            package
+                .lines()
+                .find_map(|line| line.strip_prefix("version = \""))
+                .and_then(|version| version.strip_suffix('"'))
+        })
+        .unwrap_or("unknown");
+
+    println!("cargo:rustc-env=BTLEPLUG_VERSION={version}");
+}
@@ -1,16 +1,29 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
+use adapter_info::AdapterInfo;
+use battery::BatteryLevel;
 use btleplug::{
     api::{Central, CentralEvent, Characteristic, Manager as _, Peripheral},
     platform::{Adapter, Manager, Peripheral as PlatformPeripheral},
     Error,
 };
-use characteristic_value::CharacteristicValue;
+use characteristic_value::{CharacteristicValue, ValueSource};
 use connected_device::ConnectedDevice;
+use cube::{
+    cube_event::{CubeEvent, DeviceCubeEvent},
+    cube_state::CubeState,
+    move_tracker::HISTORY_TIMEOUT,
+    CubeCapabilities, CubeCommand, SmartCube,
+};
 use device_data::DeviceData;
-use discovery::discovery_stream::DiscoveryStream;
+use device_registry::DeviceRegistry;
+use discovery::{
+    advertisement_stream::AdvertisementStream, device_filter::DeviceFilter,
+    discovered_device::DiscoveredDevice, discovery_stream::DiscoveryStream,
+};
 use futures_util::StreamExt;
 use notifications::notification_stream::NotificationStream;
+use timer::timer_event::{DeviceTimerEvent, TimerHistory};
 use tokio::sync::{
     broadcast,
     mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
@@ -18,20 +31,33 @@ use tokio::sync::{
 };
 use tracing::{error, info, warn};
 use uuid::Uuid;
+use write_queue::{
+    write_sequence::{WriteOperation, WriteOptions, WriteProgress, WriteReport},
+    WriteQueue,
+};
 
 use self::discovery::Discovery;
 
+pub mod adapter_info;
+pub mod assigned_numbers;
+pub mod battery;
 pub mod characteristic_value;
 pub mod connected_device;
+pub mod cube;
 pub mod device_data;
+mod device_registry;
 pub mod discovery;
 pub mod error;
 pub mod notifications;
+pub mod timer;
 mod timestamp;
+pub mod write_queue;
 
 enum BluetoothMessage {
     SubscribeToDiscovery(oneshot::Sender<Result<DiscoveryStream, Error>>),
     UnsubscribeFromDiscovery,
+    SubscribeToAdvertisements(oneshot::Sender<Result<AdvertisementStream, Error>>),
+    AdapterInfo(oneshot::Sender<Result<AdapterInfo, Error>>),
     Connect(String, oneshot::Sender<Result<DeviceData, Error>>),
     Disconnect(String, oneshot::Sender<Result<(), Error>>),
     ReadCharacteristic(
@@ -40,12 +66,26 @@ enum BluetoothMessage {
         oneshot::Sender<Result<CharacteristicValue, Error>>,
     ),
     WriteCharacteristic(String, Uuid, Vec<u8>, oneshot::Sender<Result<(), Error>>),
+    WriteQueue(String, oneshot::Sender<Result<WriteQueue, Error>>),
     SubscribeToCharacteristic(
         String,
         Uuid,
         oneshot::Sender<Result<NotificationStream, Error>>,
     ),
     UnsubscribeFromCharacteristic(String, Uuid, oneshot::Sender<Result<(), Error>>),
+    /// Sent without a result channel when the device itself reports a GATT change.
+    RefreshServices(String, Option<oneshot::Sender<Result<DeviceData, Error>>>),
+    BatteryLevelChanged(String, u8),
+    /// Sent by cube drivers for every event they decode.
+    CubeEventDecoded(DeviceCubeEvent),
+    /// Sent once a cube has had its time to replay lost moves.
+    CubeHistoryTimeout(String),
+    /// Sent when a cube couldn't be asked to replay lost moves.
+    CubeHistoryFailed(String),
+    CubeCommand(String, CubeCommand, oneshot::Sender<Result<(), Error>>),
+    CubeCapabilities(String, oneshot::Sender<Result<CubeCapabilities, Error>>),
+    CubeState(String, oneshot::Sender<Result<Option<CubeState>, Error>>),
+    TimerHistory(String, oneshot::Sender<Result<TimerHistory, Error>>),
 }
 
 pub(crate) async fn adapter() -> Result<Adapter, Error> {
@@ -61,26 +101,93 @@ pub(crate) async fn adapter() -> Result<Adapter, Error> {
 #[derive(Clone)]
 pub(crate) struct Bluetooth {
     tx: UnboundedSender<BluetoothMessage>,
+    registry: DeviceRegistry,
+    broadcasts: Broadcasts,
+}
+
+/// Channels on which the actor publishes what connected devices report.
+#[derive(Clone)]
+struct Broadcasts {
     disconnect_tx: broadcast::Sender<String>,
+    services_changed_tx: broadcast::Sender<DeviceData>,
+    battery_tx: broadcast::Sender<BatteryLevel>,
+    cube_tx: broadcast::Sender<DeviceCubeEvent>,
+    timer_tx: broadcast::Sender<DeviceTimerEvent>,
+}
+
+impl Broadcasts {
+    fn new() -> Self {
+        let (disconnect_tx, _) = broadcast::channel(16);
+        let (services_changed_tx, _) = broadcast::channel(16);
+        let (battery_tx, _) = broadcast::channel(16);
+        // Gyro frames arrive many times a second
+        let (cube_tx, _) = broadcast::channel(256);
+        let (timer_tx, _) = broadcast::channel(16);
+
+        Self {
+            disconnect_tx,
+            services_changed_tx,
+            battery_tx,
+            cube_tx,
+            timer_tx,
+        }
+    }
 }
 
 impl Bluetooth {
     pub fn start(adapter: Adapter) -> Self {
         let (tx, rx) = unbounded_channel();
-        let (disconnect_tx, _) = broadcast::channel(16);
+        let broadcasts = Broadcasts::new();
 
-        let discovery = Discovery::start(adapter.clone());
-        let mut actor = BluetoothActor::new(adapter, discovery, disconnect_tx.clone());
+        let registry = DeviceRegistry::load();
+        let discovery = Discovery::start(adapter.clone(), registry.clone());
+        let mut actor = BluetoothActor::new(
+            adapter,
+            discovery,
+            registry.clone(),
+            tx.clone(),
+            broadcasts.clone(),
+        );
 
         tokio::spawn(async move {
             actor.run(rx).await;
         });
 
-        Self { tx, disconnect_tx }
+        Self {
+            tx,
+            registry,
+            broadcasts,
+        }
+    }
+
+    /// Resolves a stable device ID or one of its platform ID aliases to the
+    /// stable ID used in all responses and broadcasts.
+    pub fn stable_id(&self, device_id: &str) -> String {
+        self.registry.stable_id(device_id)
     }
 
     pub fn subscribe_to_disconnections(&self) -> broadcast::Receiver<String> {
-        self.disconnect_tx.subscribe()
+        self.broadcasts.disconnect_tx.subscribe()
+    }
+
+    /// Receives the new `DeviceData` of every device whose services were refreshed.
+    pub fn subscribe_to_service_changes(&self) -> broadcast::Receiver<DeviceData> {
+        self.broadcasts.services_changed_tx.subscribe()
+    }
+
+    /// Receives battery levels reported by connected devices' Battery Service.
+    pub fn subscribe_to_battery_levels(&self) -> broadcast::Receiver<BatteryLevel> {
+        self.broadcasts.battery_tx.subscribe()
+    }
+
+    /// Receives the events decoded by the drivers of connected smart cubes.
+    pub fn subscribe_to_cube_events(&self) -> broadcast::Receiver<DeviceCubeEvent> {
+        self.broadcasts.cube_tx.subscribe()
+    }
+
+    /// Receives the events decoded by the drivers of connected smart timers.
+    pub fn subscribe_to_timer_events(&self) -> broadcast::Receiver<DeviceTimerEvent> {
+        self.broadcasts.timer_tx.subscribe()
     }
 
     pub async fn subscribe_to_discovery(&self) -> Result<DiscoveryStream, Error> {
@@ -98,10 +205,94 @@ impl Bluetooth {
             .expect("Failed to send message to Bluetooth actor");
     }
 
+    /// Streams every advertisement received while the subscription lasts. Scanning
+    /// continues until it is ended with `unsubscribe_from_discovery`.
+    pub async fn subscribe_to_advertisements(&self) -> Result<AdvertisementStream, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::SubscribeToAdvertisements(tx))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await
+            .expect("Failed to receive advertisements subscription response")
+    }
+
+    /// Runs discovery for `duration` and returns the devices matching `filter` that
+    /// were seen by the end of it.
+    pub async fn scan(
+        &self,
+        duration: Duration,
+        filter: &DeviceFilter,
+    ) -> Result<Vec<DiscoveredDevice>, Error> {
+        let mut discovery_stream = self.subscribe_to_discovery().await?;
+        let mut devices = vec![];
+
+        let deadline = tokio::time::sleep(duration);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                update = discovery_stream.next() => match update {
+                    Some(update) => devices = update,
+                    None => break,
+                },
+            }
+        }
+
+        self.unsubscribe_from_discovery().await;
+
+        devices.retain(|device| filter.matches(device));
+        Ok(devices)
+    }
+
+    /// Runs discovery until a device matching `filter` appears, failing with
+    /// `TimedOut` if none does within `timeout`.
+    pub async fn wait_for_device(
+        &self,
+        timeout: Duration,
+        filter: &DeviceFilter,
+    ) -> Result<DiscoveredDevice, Error> {
+        let mut discovery_stream = self.subscribe_to_discovery().await?;
+
+        let found = tokio::time::timeout(timeout, async {
+            while let Some(devices) = discovery_stream.next().await {
+                if let Some(device) = devices.into_iter().find(|device| filter.matches(device)) {
+                    return Some(device);
+                }
+            }
+
+            None
+        })
+        .await;
+
+        self.unsubscribe_from_discovery().await;
+
+        match found {
+            Ok(Some(device)) => Ok(device),
+            Ok(None) => Err(Error::RuntimeError(
+                "Discovery ended unexpectedly".to_string(),
+            )),
+            Err(_) => Err(Error::TimedOut(timeout)),
+        }
+    }
+
+    pub async fn adapter_info(&self) -> Result<AdapterInfo, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::AdapterInfo(tx))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await.expect("Failed to receive adapter info response")
+    }
+
     pub async fn connect(&self, device_id: &str) -> Result<DeviceData, Error> {
         let (tx, rx) = oneshot::channel();
         self.tx
-            .send(BluetoothMessage::Connect(device_id.to_string(), tx))
+            .send(BluetoothMessage::Connect(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
             .expect("Failed to send message to Bluetooth actor");
 
         rx.await.expect("Failed to receive connect response")
@@ -110,12 +301,28 @@ impl Bluetooth {
     pub async fn disconnect(&self, device_id: &str) -> Result<(), Error> {
         let (tx, rx) = oneshot::channel();
         self.tx
-            .send(BluetoothMessage::Disconnect(device_id.to_string(), tx))
+            .send(BluetoothMessage::Disconnect(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
             .expect("Failed to send message to Bluetooth actor");
 
         rx.await.expect("Failed to receive disconnect response")
     }
 
+    pub async fn refresh_services(&self, device_id: &str) -> Result<DeviceData, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::RefreshServices(
+                self.registry.stable_id(device_id),
+                Some(tx),
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await
+            .expect("Failed to receive refresh services response")
+    }
+
     pub async fn read_characteristic(
         &self,
         device_id: &str,
@@ -124,7 +331,7 @@ impl Bluetooth {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(BluetoothMessage::ReadCharacteristic(
-                device_id.to_string(),
+                self.registry.stable_id(device_id),
                 characteristic_id,
                 tx,
             ))
@@ -142,7 +349,7 @@ impl Bluetooth {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(BluetoothMessage::WriteCharacteristic(
-                device_id.to_string(),
+                self.registry.stable_id(device_id),
                 characteristic_id,
                 value,
                 tx,
@@ -153,6 +360,85 @@ impl Bluetooth {
             .expect("Failed to receive write characteristic response")
     }
 
+    /// Sends a command to the driver of a connected smart cube.
+    pub async fn cube_command(&self, device_id: &str, command: CubeCommand) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::CubeCommand(
+                self.registry.stable_id(device_id),
+                command,
+                tx,
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await.expect("Failed to receive cube command response")
+    }
+
+    /// What the driver of a connected smart cube supports. Fails with
+    /// `Error::NotSupported` for devices without a cube driver.
+    pub async fn cube_capabilities(&self, device_id: &str) -> Result<CubeCapabilities, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::CubeCapabilities(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await
+            .expect("Failed to receive cube capabilities response")
+    }
+
+    /// The state of a connected smart cube, as tracked from its moves and
+    /// facelets. `None` until the cube has reported its facelets.
+    pub async fn cube_state(&self, device_id: &str) -> Result<Option<CubeState>, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::CubeState(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await.expect("Failed to receive cube state response")
+    }
+
+    /// Reads the times stored on a connected smart timer.
+    pub async fn timer_history(&self, device_id: &str) -> Result<TimerHistory, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::TimerHistory(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        rx.await.expect("Failed to receive timer history response")
+    }
+
+    /// Queues a sequence of writes on the device. Sequences for the same device
+    /// are written one after another, in the order they were requested.
+    pub async fn write_sequence(
+        &self,
+        device_id: &str,
+        writes: Vec<WriteOperation>,
+        options: WriteOptions,
+        progress: UnboundedSender<WriteProgress>,
+    ) -> Result<WriteReport, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(BluetoothMessage::WriteQueue(
+                self.registry.stable_id(device_id),
+                tx,
+            ))
+            .expect("Failed to send message to Bluetooth actor");
+
+        // The sequence is awaited outside of the actor so that a long sequence
+        // does not hold up requests for other devices.
+        let write_queue = rx.await.expect("Failed to receive write queue response")?;
+        write_queue.write_sequence(writes, options, progress).await
+    }
+
     pub async fn subscribe_to_characteristic(
         &self,
         device_id: &str,
@@ -161,7 +447,7 @@ impl Bluetooth {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(BluetoothMessage::SubscribeToCharacteristic(
-                device_id.to_string(),
+                self.registry.stable_id(device_id),
                 characteristic_id,
                 tx,
             ))
@@ -178,7 +464,7 @@ impl Bluetooth {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send(BluetoothMessage::UnsubscribeFromCharacteristic(
-                device_id.to_string(),
+                self.registry.stable_id(device_id),
                 characteristic_id,
                 tx,
             ))
@@ -192,21 +478,27 @@ impl Bluetooth {
 pub(crate) struct BluetoothActor {
     adapter: Adapter,
     discovery: Discovery,
+    registry: DeviceRegistry,
     connected_devices: HashMap<String, ConnectedDevice>,
-    disconnect_tx: broadcast::Sender<String>,
+    self_tx: UnboundedSender<BluetoothMessage>,
+    broadcasts: Broadcasts,
 }
 
 impl BluetoothActor {
     fn new(
         adapter: Adapter,
         discovery: Discovery,
-        disconnect_tx: broadcast::Sender<String>,
+        registry: DeviceRegistry,
+        self_tx: UnboundedSender<BluetoothMessage>,
+        broadcasts: Broadcasts,
     ) -> Self {
         Self {
             adapter,
             discovery,
+            registry,
             connected_devices: HashMap::new(),
-            disconnect_tx,
+            self_tx,
+            broadcasts,
         }
     }
 
@@ -230,6 +522,12 @@ impl BluetoothActor {
                         BluetoothMessage::UnsubscribeFromDiscovery => {
                             self.handle_unsubscribe_from_discovery().await;
                         }
+                        BluetoothMessage::SubscribeToAdvertisements(result_tx) => {
+                            self.handle_subscribe_to_advertisements(result_tx).await;
+                        }
+                        BluetoothMessage::AdapterInfo(result_tx) => {
+                            self.handle_adapter_info(result_tx).await;
+                        }
                         BluetoothMessage::Connect(device_id, result_tx) => {
                             self.handle_connect(device_id, result_tx).await;
                         }
@@ -244,6 +542,9 @@ impl BluetoothActor {
                             self.handle_write_characteristic(device_id, uuid, value, sender)
                                 .await;
                         }
+                        BluetoothMessage::WriteQueue(device_id, sender) => {
+                            self.handle_write_queue(device_id, sender);
+                        }
                         BluetoothMessage::SubscribeToCharacteristic(device_id, uuid, sender) => {
                             self.handle_subscribe_to_characteristic(device_id, uuid, sender)
                                 .await;
@@ -252,6 +553,33 @@ impl BluetoothActor {
                             self.handle_unsubscribe_from_characteristic(device_id, uuid, sender)
                                 .await;
                         }
+                        BluetoothMessage::RefreshServices(device_id, sender) => {
+                            self.handle_refresh_services(device_id, sender).await;
+                        }
+                        BluetoothMessage::BatteryLevelChanged(device_id, level) => {
+                            self.handle_battery_level_changed(device_id, level);
+                        }
+                        BluetoothMessage::CubeEventDecoded(event) => {
+                            self.handle_cube_event_decoded(event);
+                        }
+                        BluetoothMessage::CubeHistoryTimeout(device_id) => {
+                            self.handle_cube_history_timeout(device_id);
+                        }
+                        BluetoothMessage::CubeHistoryFailed(device_id) => {
+                            self.handle_cube_history_failed(device_id);
+                        }
+                        BluetoothMessage::CubeCommand(device_id, command, sender) => {
+                            self.handle_cube_command(device_id, command, sender).await;
+                        }
+                        BluetoothMessage::CubeCapabilities(device_id, sender) => {
+                            self.handle_cube_capabilities(device_id, sender);
+                        }
+                        BluetoothMessage::CubeState(device_id, sender) => {
+                            self.handle_cube_state(device_id, sender);
+                        }
+                        BluetoothMessage::TimerHistory(device_id, sender) => {
+                            self.handle_timer_history(device_id, sender).await;
+                        }
                     }
                 },
                 event = events.next() => {
@@ -283,6 +611,28 @@ impl BluetoothActor {
         self.discovery.unsubscribe().await;
     }
 
+    async fn handle_subscribe_to_advertisements(
+        &mut self,
+        result_tx: oneshot::Sender<Result<AdvertisementStream, Error>>,
+    ) {
+        if result_tx
+            .send(self.discovery.subscribe_to_advertisements().await)
+            .is_err()
+        {
+            error!("Failed to send advertisements subscription result");
+        }
+    }
+
+    async fn handle_adapter_info(&self, result_tx: oneshot::Sender<Result<AdapterInfo, Error>>) {
+        let scanning = self.discovery.is_scanning().await;
+        let result =
+            adapter_info::adapter_info(&self.adapter, scanning, self.connected_devices.len()).await;
+
+        if result_tx.send(result).is_err() {
+            error!("Failed to send adapter info result");
+        }
+    }
+
     async fn handle_connect(
         &mut self,
         device_id: String,
@@ -328,6 +678,104 @@ impl BluetoothActor {
         }
     }
 
+    async fn handle_cube_command(
+        &mut self,
+        device_id: String,
+        command: CubeCommand,
+        sender: oneshot::Sender<Result<(), Error>>,
+    ) {
+        let result = match self.connected_devices.get_mut(&device_id) {
+            Some(ConnectedDevice {
+                cube: Some(cube),
+                cube_state,
+                ..
+            }) => {
+                let result = cube.send(command).await;
+                if result.is_ok() && matches!(command, CubeCommand::ResetSolved) {
+                    *cube_state = Some(CubeState::solved());
+                }
+                result
+            }
+            Some(_) => Err(Error::NotSupported(format!(
+                "{device_id} is not a supported smart cube"
+            ))),
+            None => Err(Error::DeviceNotFound),
+        };
+        if sender.send(result).is_err() {
+            error!("Failed to send cube command result");
+        }
+    }
+
+    fn handle_cube_capabilities(
+        &self,
+        device_id: String,
+        sender: oneshot::Sender<Result<CubeCapabilities, Error>>,
+    ) {
+        let result = match self.connected_devices.get(&device_id) {
+            Some(ConnectedDevice {
+                cube: Some(cube), ..
+            }) => Ok(cube.capabilities()),
+            Some(_) => Err(Error::NotSupported(format!(
+                "{device_id} is not a supported smart cube"
+            ))),
+            None => Err(Error::DeviceNotFound),
+        };
+        if sender.send(result).is_err() {
+            error!("Failed to send cube capabilities result");
+        }
+    }
+
+    fn handle_cube_state(
+        &self,
+        device_id: String,
+        sender: oneshot::Sender<Result<Option<CubeState>, Error>>,
+    ) {
+        let result = match self.connected_devices.get(&device_id) {
+            Some(device) if device.cube.is_some() => Ok(device.cube_state.clone()),
+            Some(_) => Err(Error::NotSupported(format!(
+                "{device_id} is not a supported smart cube"
+            ))),
+            None => Err(Error::DeviceNotFound),
+        };
+        if sender.send(result).is_err() {
+            error!("Failed to send cube state result");
+        }
+    }
+
+    async fn handle_timer_history(
+        &self,
+        device_id: String,
+        sender: oneshot::Sender<Result<TimerHistory, Error>>,
+    ) {
+        let result = match self.connected_devices.get(&device_id) {
+            Some(ConnectedDevice {
+                timer: Some(timer), ..
+            }) => timer.history().await,
+            Some(_) => Err(Error::NotSupported(format!(
+                "{device_id} is not a supported smart timer"
+            ))),
+            None => Err(Error::DeviceNotFound),
+        };
+        if sender.send(result).is_err() {
+            error!("Failed to send timer history result");
+        }
+    }
+
+    fn handle_write_queue(
+        &self,
+        device_id: String,
+        sender: oneshot::Sender<Result<WriteQueue, Error>>,
+    ) {
+        let result = self
+            .connected_devices
+            .get(&device_id)
+            .map(|device| device.writes.clone())
+            .ok_or(Error::DeviceNotFound);
+        if sender.send(result).is_err() {
+            error!("Failed to send write queue result");
+        }
+    }
+
     async fn handle_subscribe_to_characteristic(
         &mut self,
         device_id: String,
@@ -352,12 +800,110 @@ impl BluetoothActor {
         }
     }
 
+    async fn handle_refresh_services(
+        &mut self,
+        device_id: String,
+        sender: Option<oneshot::Sender<Result<DeviceData, Error>>>,
+    ) {
+        let result = self.refresh_services(device_id).await;
+
+        match sender {
+            Some(sender) => {
+                if sender.send(result).is_err() {
+                    error!("Failed to send refresh services result");
+                }
+            }
+            None => {
+                if let Err(err) = result {
+                    warn!("Failed to refresh services after Service Changed: {err:?}");
+                }
+            }
+        }
+    }
+
+    fn handle_battery_level_changed(&mut self, device_id: String, level: u8) {
+        let Some(device) = self.connected_devices.get_mut(&device_id) else {
+            return;
+        };
+        device.battery_level = Some(level);
+
+        // Nobody listening is fine, the level is kept for the next DeviceData.
+        let _ = self
+            .broadcasts
+            .battery_tx
+            .send(BatteryLevel::new(device_id, level));
+    }
+
+    /// Keeps the tracked state of the cube up to date before passing the
+    /// events on.
+    fn handle_cube_event_decoded(&mut self, event: DeviceCubeEvent) {
+        let DeviceCubeEvent {
+            device_id,
+            timestamp,
+            event,
+        } = event;
+        let Some(device) = self.connected_devices.get_mut(&device_id) else {
+            return;
+        };
+
+        let was_recovering = device.cube_moves.is_recovering();
+        let events = device.track_cube_event(event, &self.self_tx);
+        if !was_recovering && device.cube_moves.is_recovering() {
+            let self_tx = self.self_tx.clone();
+            let recovering_device_id = device_id.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(HISTORY_TIMEOUT).await;
+                let _ = self_tx.send(BluetoothMessage::CubeHistoryTimeout(recovering_device_id));
+            });
+        }
+
+        self.broadcast_cube_events(device_id, timestamp, events);
+    }
+
+    /// Gives up on lost moves if the cube still hasn't replayed them.
+    fn handle_cube_history_timeout(&mut self, device_id: String) {
+        let Some(device) = self.connected_devices.get_mut(&device_id) else {
+            return;
+        };
+        if !device.cube_moves.is_overdue() {
+            return; // recovered, or recovering from a later gap
+        }
+
+        warn!("{device_id} didn't replay its lost moves in time");
+        let events = device.desync_cube();
+        self.broadcast_cube_events(device_id, timestamp::timestamp(), events);
+    }
+
+    /// Gives up on lost moves the cube couldn't be asked to replay.
+    fn handle_cube_history_failed(&mut self, device_id: String) {
+        let Some(device) = self.connected_devices.get_mut(&device_id) else {
+            return;
+        };
+        if !device.cube_moves.is_recovering() {
+            return; // already given up on by the timeout
+        }
+
+        let events = device.desync_cube();
+        self.broadcast_cube_events(device_id, timestamp::timestamp(), events);
+    }
+
+    fn broadcast_cube_events(&self, device_id: String, timestamp: u64, events: Vec<CubeEvent>) {
+        for event in events {
+            // Nobody listening is fine, the state is kept for get-cube-state.
+            let _ = self.broadcasts.cube_tx.send(DeviceCubeEvent {
+                device_id: device_id.clone(),
+                timestamp,
+                event,
+            });
+        }
+    }
+
     async fn handle_device_disconnected(&mut self, id: btleplug::platform::PeripheralId) {
-        let id_str = id.to_string();
+        let id_str = self.registry.stable_id(&id.to_string());
         if let Some(device) = self.connected_devices.remove(&id_str) {
             warn!("Device {id_str} disconnected unexpectedly");
-            device.notifications.stop().await;
-            let _ = self.disconnect_tx.send(id_str);
+            device.stop().await;
+            let _ = self.broadcasts.disconnect_tx.send(id_str);
         }
     }
 
@@ -367,67 +913,35 @@ impl BluetoothActor {
 
             device.add_client();
 
+            // Lets the new client catch up, and restarts cubes that stalled
+            if let Some(cube) = &device.cube {
+                if let Err(err) = cube.send(CubeCommand::RequestFacelets).await {
+                    warn!("Failed to request facelets of {device_id}: {err:?}");
+                }
+            }
+
             return Ok((&*device).into());
         }
 
         let peripherals = self.adapter.peripherals().await?;
 
         for peripheral in peripherals {
-            if device_id == peripheral.id().to_string() {
+            let platform_id = peripheral.id().to_string();
+            if device_id == platform_id || device_id == self.registry.stable_id(&platform_id) {
                 info!("Found device: {device_id}");
 
-                // On BlueZ the Connect() D-Bus call performs service discovery
-                // internally, so "ServiceDiscoveryTimedOut" surfaces here rather
-                // than in discover_services(). Retry a few times with a back-off
-                // before giving up.
-                const CONNECT_RETRIES: u32 = 3;
-                const CONNECT_RETRY_DELAY_MS: u64 = 2_000;
-
-                if !peripheral.is_connected().await.unwrap_or(false) {
-                    let mut connect_err = Error::DeviceNotFound;
-                    let mut connected = false;
-                    for attempt in 1..=CONNECT_RETRIES {
-                        match peripheral.connect().await {
-                            Ok(()) => {
-                                if attempt > 1 {
-                                    info!("Connected to {device_id} on attempt {attempt}");
-                                } else {
-                                    info!("Connected to {device_id}");
-                                }
-                                connected = true;
-                                break;
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "Connect attempt {attempt}/{CONNECT_RETRIES} for \
-                                     {device_id} failed: {e:?}"
-                                );
-                                connect_err = e;
-                                if attempt < CONNECT_RETRIES {
-                                    // BlueZ keeps the failed attempt alive internally
-                                    // ("Operation already in progress"), so we must
-                                    // disconnect to purge the stale state before retrying.
-                                    if let Err(de) = peripheral.disconnect().await {
-                                        warn!("Cleanup disconnect after failed connect attempt {attempt} failed: {de:?}");
-                                    }
-                                    tokio::time::sleep(std::time::Duration::from_millis(
-                                        CONNECT_RETRY_DELAY_MS,
-                                    ))
-                                    .await;
-                                }
-                            }
-                        }
-                    }
-                    if !connected {
-                        return Err(connect_err);
-                    }
-                } else {
-                    info!("Device {device_id} already connected at OS level, skipping connect()");
-                }
+                // The device may not have been seen by discovery yet, in which case
+                // it only gets its stable ID now.
+                let properties = peripheral.properties().await?;
+                let device_id = self.registry.register(&platform_id, properties.as_ref());
+
+                Self::connect_with_retries(&peripheral, &device_id).await?;
 
                 let mut connected_device =
                     ConnectedDevice::start(peripheral.clone(), device_id.clone()).await?;
 
+                self.watch_device(&mut connected_device, &device_id).await;
+
                 connected_device.add_client();
 
                 let device_data: DeviceData = (&connected_device).into();
@@ -441,6 +955,108 @@ impl BluetoothActor {
         Err(Error::DeviceNotFound)
     }
 
+    async fn connect_with_retries(
+        peripheral: &PlatformPeripheral,
+        device_id: &str,
+    ) -> Result<(), Error> {
+        // On BlueZ the Connect() D-Bus call performs service discovery
+        // internally, so "ServiceDiscoveryTimedOut" surfaces here rather
+        // than in discover_services(). Retry a few times with a back-off
+        // before giving up.
+        const CONNECT_RETRIES: u32 = 3;
+        const CONNECT_RETRY_DELAY_MS: u64 = 2_000;
+
+        if !peripheral.is_connected().await.unwrap_or(false) {
+            let mut connect_err = Error::DeviceNotFound;
+            let mut connected = false;
+            for attempt in 1..=CONNECT_RETRIES {
+                match peripheral.connect().await {
+                    Ok(()) => {
+                        if attempt > 1 {
+                            info!("Connected to {device_id} on attempt {attempt}");
+                        } else {
+                            info!("Connected to {device_id}");
+                        }
+                        connected = true;
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Connect attempt {attempt}/{CONNECT_RETRIES} for \
+                             {device_id} failed: {e:?}"
+                        );
+                        connect_err = e;
+                        if attempt < CONNECT_RETRIES {
+                            // BlueZ keeps the failed attempt alive internally
+                            // ("Operation already in progress"), so we must
+                            // disconnect to purge the stale state before retrying.
+                            if let Err(de) = peripheral.disconnect().await {
+                                warn!("Cleanup disconnect after failed connect attempt {attempt} failed: {de:?}");
+                            }
+                            tokio::time::sleep(std::time::Duration::from_millis(
+                                CONNECT_RETRY_DELAY_MS,
+                            ))
+                            .await;
+                        }
+                    }
+                }
+            }
+            if !connected {
+                return Err(connect_err);
+            }
+        } else {
+            info!("Device {device_id} already connected at OS level, skipping connect()");
+        }
+
+        Ok(())
+    }
+
+    /// Forwards the service changes, battery levels and decoded cube and timer
+    /// events of a newly connected device.
+    async fn watch_device(&self, device: &mut ConnectedDevice, device_id: &str) {
+        let self_tx = self.self_tx.clone();
+        let changed_device_id = device_id.to_string();
+        device
+            .watch_service_changed(move || {
+                info!("Services of {changed_device_id} changed");
+                let _ = self_tx.send(BluetoothMessage::RefreshServices(
+                    changed_device_id.clone(),
+                    None,
+                ));
+            })
+            .await;
+
+        let self_tx = self.self_tx.clone();
+        let battery_device_id = device_id.to_string();
+        device
+            .watch_battery_level(move |level| {
+                let _ = self_tx.send(BluetoothMessage::BatteryLevelChanged(
+                    battery_device_id.clone(),
+                    level,
+                ));
+            })
+            .await;
+
+        let self_tx = self.self_tx.clone();
+        let cube_device_id = device_id.to_string();
+        device
+            .watch_cube(move |event| {
+                let _ = self_tx.send(BluetoothMessage::CubeEventDecoded(DeviceCubeEvent::new(
+                    cube_device_id.clone(),
+                    event,
+                )));
+            })
+            .await;
+
+        let timer_tx = self.broadcasts.timer_tx.clone();
+        let timer_device_id = device_id.to_string();
+        device
+            .watch_timer(move |event| {
+                let _ = timer_tx.send(DeviceTimerEvent::new(timer_device_id.clone(), event));
+            })
+            .await;
+    }
+
     async fn disconnect(&mut self, device_id: String) -> Result<(), Error> {
         if let Some(device) = self.connected_devices.get_mut(&device_id) {
             device.remove_client();
@@ -450,7 +1066,7 @@ impl BluetoothActor {
                 info!("Disconnected from {device_id}");
 
                 let device = device.unwrap();
-                device.notifications.stop().await;
+                device.stop().await;
                 device.peripheral.disconnect().await
             } else {
                 Ok(())
@@ -462,6 +1078,24 @@ impl BluetoothActor {
         }
     }
 
+    async fn refresh_services(&mut self, device_id: String) -> Result<DeviceData, Error> {
+        let device = self
+            .connected_devices
+            .get_mut(&device_id)
+            .ok_or(Error::DeviceNotFound)?;
+
+        device.refresh_services().await?;
+
+        let device_data: DeviceData = (&*device).into();
+        // Nobody listening is fine, the caller still gets the new data.
+        let _ = self
+            .broadcasts
+            .services_changed_tx
+            .send(device_data.clone());
+
+        Ok(device_data)
+    }
+
     async fn characteristic(
         &self,
         device_id: String,
@@ -494,6 +1128,7 @@ impl BluetoothActor {
         Ok(CharacteristicValue {
             timestamp: timestamp::timestamp(),
             value,
+            source: ValueSource::Read,
         })
     }
 
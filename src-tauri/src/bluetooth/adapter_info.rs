@@ -0,0 +1,135 @@
+use btleplug::{
+    api::{Central as _, CentralState},
+    platform::Adapter,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+/// Diagnostic snapshot of the Bluetooth stack the proxy is running on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterInfo {
+    /// Adapter description as reported by the platform, e.g. `hci0 (usb:v1D6Bp0246d0548)`.
+    pub name: String,
+    /// Only BlueZ makes the adapter address available.
+    pub address: Option<String>,
+    pub backend: Backend,
+    /// Version of btleplug the proxy was built with.
+    pub btleplug_version: String,
+    /// `None` when the platform can't tell whether the adapter is powered.
+    pub powered: Option<bool>,
+    pub scanning: bool,
+    pub connected_devices: usize,
+    pub features: Vec<Feature>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[serde(rename = "bluez")]
+    BlueZ,
+    CoreBluetooth,
+    #[serde(rename = "winrt")]
+    WinRt,
+    Android,
+}
+
+/// Optional capabilities a client may want to check before relying on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// Platform peripheral addresses are real MAC addresses.
+    PeripheralAddresses,
+    Indications,
+    WriteSequence,
+    RefreshServices,
+    StableIds,
+    DeviceInfo,
+    BatteryLevel,
+    GattNames,
+    Scan,
+    Advertisements,
+    DeviceKind,
+    CubeEvents,
+    TimerEvents,
+}
+
+impl Backend {
+    pub fn current() -> Self {
+        if cfg!(target_os = "linux") {
+            Self::BlueZ
+        } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+            Self::CoreBluetooth
+        } else if cfg!(target_os = "android") {
+            Self::Android
+        } else {
+            Self::WinRt
+        }
+    }
+}
+
+impl Feature {
+    pub fn supported() -> Vec<Self> {
+        let mut features = vec![
+            Self::Indications,
+            Self::WriteSequence,
+            Self::RefreshServices,
+            Self::StableIds,
+            Self::DeviceInfo,
+            Self::BatteryLevel,
+            Self::GattNames,
+            Self::Scan,
+            Self::Advertisements,
+            Self::DeviceKind,
+            Self::CubeEvents,
+            Self::TimerEvents,
+        ];
+
+        // CoreBluetooth hides peripheral addresses behind per-host UUIDs
+        if !matches!(Backend::current(), Backend::CoreBluetooth) {
+            features.push(Self::PeripheralAddresses);
+        }
+
+        features
+    }
+}
+
+pub(super) async fn adapter_info(
+    adapter: &Adapter,
+    scanning: bool,
+    connected_devices: usize,
+) -> Result<AdapterInfo, Error> {
+    let name = adapter.adapter_info().await?;
+    let powered = match adapter.adapter_state().await? {
+        CentralState::PoweredOn => Some(true),
+        CentralState::PoweredOff => Some(false),
+        CentralState::Unknown => None,
+    };
+
+    let address = address(&name).await;
+
+    Ok(AdapterInfo {
+        name,
+        address,
+        backend: Backend::current(),
+        btleplug_version: env!("BTLEPLUG_VERSION").to_string(),
+        powered,
+        scanning,
+        connected_devices,
+        features: Feature::supported(),
+    })
+}
+
+/// Reads the address of a BlueZ adapter from sysfs, by the adapter ID at the
+/// start of its name.
+async fn address(name: &str) -> Option<String> {
+    if !matches!(Backend::current(), Backend::BlueZ) {
+        return None;
+    }
+
+    let id = name.split_whitespace().next()?;
+    let address = tokio::fs::read_to_string(format!("/sys/class/bluetooth/{id}/address"))
+        .await
+        .ok()?;
+
+    Some(address.trim().to_uppercase())
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
+
+use btleplug::api::bleuuid::BleUuid as _;
+use uuid::Uuid;
+
+mod sig;
+mod vendor;
+
+/// Names for vendor-specific UUIDs, seeded with the built-in vendor table and
+/// extended at runtime by clients.
+fn vendor_names() -> &'static RwLock<HashMap<Uuid, String>> {
+    static VENDOR_NAMES: OnceLock<RwLock<HashMap<Uuid, String>>> = OnceLock::new();
+
+    VENDOR_NAMES.get_or_init(|| {
+        let names = vendor::NAMES
+            .iter()
+            .map(|(uuid, name)| {
+                let uuid = Uuid::parse_str(uuid).expect("Invalid UUID in vendor table");
+                (uuid, (*name).to_string())
+            })
+            .collect();
+
+        RwLock::new(names)
+    })
+}
+
+/// Registers a human-readable name for a vendor-specific service, characteristic
+/// or descriptor UUID. Later registrations replace earlier ones.
+pub fn register_vendor_name(uuid: Uuid, name: String) {
+    vendor_names()
+        .write()
+        .expect("Vendor names lock poisoned")
+        .insert(uuid, name);
+}
+
+pub fn service_name(uuid: &Uuid) -> Option<String> {
+    name(uuid, sig::SERVICES)
+}
+
+pub fn characteristic_name(uuid: &Uuid) -> Option<String> {
+    name(uuid, sig::CHARACTERISTICS)
+}
+
+pub fn descriptor_name(uuid: &Uuid) -> Option<String> {
+    name(uuid, sig::DESCRIPTORS)
+}
+
+pub fn company_name(company_id: u16) -> Option<&'static str> {
+    lookup(company_id, sig::COMPANIES)
+}
+
+fn name(uuid: &Uuid, table: &[(u16, &'static str)]) -> Option<String> {
+    if let Some(name) = vendor_names()
+        .read()
+        .expect("Vendor names lock poisoned")
+        .get(uuid)
+    {
+        return Some(name.clone());
+    }
+
+    // SIG numbers only apply to UUIDs derived from the Bluetooth base UUID
+    lookup(uuid.to_ble_u16()?, table).map(str::to_string)
+}
+
+fn lookup(number: u16, table: &[(u16, &'static str)]) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(assigned, _)| *assigned == number)
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,128 @@
+//! Subset of the Bluetooth SIG assigned numbers
+//! (https://www.bluetooth.com/specifications/assigned-numbers/), limited to
+//! what is likely to show up on cubes, timers and the hosts they talk to.
+
+pub(super) const SERVICES: &[(u16, &str)] = &[
+    (0x1800, "Generic Access"),
+    (0x1801, "Generic Attribute"),
+    (0x1802, "Immediate Alert"),
+    (0x1803, "Link Loss"),
+    (0x1804, "Tx Power"),
+    (0x1805, "Current Time"),
+    (0x1806, "Reference Time Update"),
+    (0x1807, "Next DST Change"),
+    (0x1808, "Glucose"),
+    (0x1809, "Health Thermometer"),
+    (0x180A, "Device Information"),
+    (0x180D, "Heart Rate"),
+    (0x180E, "Phone Alert Status"),
+    (0x180F, "Battery"),
+    (0x1810, "Blood Pressure"),
+    (0x1811, "Alert Notification"),
+    (0x1812, "Human Interface Device"),
+    (0x1813, "Scan Parameters"),
+    (0x1814, "Running Speed and Cadence"),
+    (0x1815, "Automation IO"),
+    (0x1816, "Cycling Speed and Cadence"),
+    (0x1818, "Cycling Power"),
+    (0x1819, "Location and Navigation"),
+    (0x181A, "Environmental Sensing"),
+    (0x181B, "Body Composition"),
+    (0x181C, "User Data"),
+    (0x181D, "Weight Scale"),
+    (0x181E, "Bond Management"),
+    (0x181F, "Continuous Glucose Monitoring"),
+    (0x1820, "Internet Protocol Support"),
+    (0x1821, "Indoor Positioning"),
+    (0x1822, "Pulse Oximeter"),
+    (0x1823, "HTTP Proxy"),
+    (0x1824, "Transport Discovery"),
+    (0x1825, "Object Transfer"),
+    (0x1826, "Fitness Machine"),
+    (0x1827, "Mesh Provisioning"),
+    (0x1828, "Mesh Proxy"),
+    (0x1829, "Reconnection Configuration"),
+];
+
+pub(super) const CHARACTERISTICS: &[(u16, &str)] = &[
+    (0x2A00, "Device Name"),
+    (0x2A01, "Appearance"),
+    (0x2A02, "Peripheral Privacy Flag"),
+    (0x2A03, "Reconnection Address"),
+    (0x2A04, "Peripheral Preferred Connection Parameters"),
+    (0x2A05, "Service Changed"),
+    (0x2A06, "Alert Level"),
+    (0x2A07, "Tx Power Level"),
+    (0x2A08, "Date Time"),
+    (0x2A19, "Battery Level"),
+    (0x2A1C, "Temperature Measurement"),
+    (0x2A23, "System ID"),
+    (0x2A24, "Model Number String"),
+    (0x2A25, "Serial Number String"),
+    (0x2A26, "Firmware Revision String"),
+    (0x2A27, "Hardware Revision String"),
+    (0x2A28, "Software Revision String"),
+    (0x2A29, "Manufacturer Name String"),
+    (
+        0x2A2A,
+        "IEEE 11073-20601 Regulatory Certification Data List",
+    ),
+    (0x2A2B, "Current Time"),
+    (0x2A37, "Heart Rate Measurement"),
+    (0x2A38, "Body Sensor Location"),
+    (0x2A39, "Heart Rate Control Point"),
+    (0x2A4A, "HID Information"),
+    (0x2A4B, "Report Map"),
+    (0x2A4C, "HID Control Point"),
+    (0x2A4D, "Report"),
+    (0x2A4E, "Protocol Mode"),
+    (0x2A50, "PnP ID"),
+    (0x2AA6, "Central Address Resolution"),
+    (0x2AC9, "Resolvable Private Address Only"),
+    (0x2B29, "Client Supported Features"),
+    (0x2B2A, "Database Hash"),
+    (0x2B3A, "Server Supported Features"),
+];
+
+pub(super) const DESCRIPTORS: &[(u16, &str)] = &[
+    (0x2900, "Characteristic Extended Properties"),
+    (0x2901, "Characteristic User Description"),
+    (0x2902, "Client Characteristic Configuration"),
+    (0x2903, "Server Characteristic Configuration"),
+    (0x2904, "Characteristic Presentation Format"),
+    (0x2905, "Characteristic Aggregate Format"),
+    (0x2906, "Valid Range"),
+    (0x2907, "External Report Reference"),
+    (0x2908, "Report Reference"),
+    (0x2909, "Number of Digitals"),
+    (0x290A, "Value Trigger Setting"),
+    (0x290B, "Environmental Sensing Configuration"),
+    (0x290C, "Environmental Sensing Measurement"),
+    (0x290D, "Environmental Sensing Trigger Setting"),
+    (0x290E, "Time Trigger Setting"),
+];
+
+pub(super) const COMPANIES: &[(u16, &str)] = &[
+    (0x0000, "Ericsson AB"),
+    (0x0001, "Nokia Mobile Phones"),
+    (0x0002, "Intel Corp."),
+    (0x0003, "IBM Corp."),
+    (0x0004, "Toshiba Corp."),
+    (0x0006, "Microsoft"),
+    (0x0008, "Motorola"),
+    (0x0009, "Infineon Technologies AG"),
+    (0x000A, "Qualcomm Technologies International, Ltd. (QTIL)"),
+    (0x000D, "Texas Instruments Inc."),
+    (0x000F, "Broadcom Corporation"),
+    (0x001D, "Qualcomm"),
+    (0x004C, "Apple, Inc."),
+    (0x0059, "Nordic Semiconductor ASA"),
+    (0x0075, "Samsung Electronics Co. Ltd."),
+    (0x0087, "Garmin International, Inc."),
+    (0x00E0, "Google"),
+    (0x0131, "Cypress Semiconductor"),
+    (0x0157, "Anhui Huami Information Technology Co., Ltd."),
+    (0x0171, "Amazon.com Services, Inc."),
+    (0x02E5, "Espressif Systems (Shanghai) Co., Ltd."),
+    (0x038F, "Xiaomi Inc."),
+];
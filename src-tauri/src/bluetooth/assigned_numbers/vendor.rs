@@ -0,0 +1,23 @@
+//! Names of the proprietary services and characteristics used by supported
+//! cube and timer vendors.
+
+pub(super) const NAMES: &[(&str, &str)] = &[
+    ("6e400001-b5a3-f393-e0a9-e50e24dc4179", "GAN Gen2 Cube"),
+    ("28be4a4a-cd67-11e9-a32f-2a2ae2dbcce4", "GAN Gen2 Command"),
+    ("28be4cb6-cd67-11e9-a32f-2a2ae2dbcce4", "GAN Gen2 State"),
+    ("8653000a-43e6-47b7-9cb0-5fc21d4ae340", "GAN Gen3 Cube"),
+    ("8653000c-43e6-47b7-9cb0-5fc21d4ae340", "GAN Gen3 Command"),
+    ("8653000b-43e6-47b7-9cb0-5fc21d4ae340", "GAN Gen3 State"),
+    ("00000010-0000-fff7-fff6-fff5fff4fff0", "GAN Gen4 Cube"),
+    ("0000aadb-0000-1000-8000-00805f9b34fb", "GiiKER Cube"),
+    ("0000aadc-0000-1000-8000-00805f9b34fb", "GiiKER State"),
+    (
+        "0000aaaa-0000-1000-8000-00805f9b34fb",
+        "GiiKER Command Service",
+    ),
+    (
+        "0000aaab-0000-1000-8000-00805f9b34fb",
+        "GiiKER Command Response",
+    ),
+    ("0000aaac-0000-1000-8000-00805f9b34fb", "GiiKER Command"),
+];
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use btleplug::{
+    api::{bleuuid::uuid_from_u16, CharPropFlags, Characteristic, Peripheral as _},
+    platform::Peripheral,
+};
+use futures_util::{FutureExt as _, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use tokio::{select, sync::oneshot};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::{notifications::Notifications, timestamp::timestamp};
+
+/// Battery Service
+const BATTERY_SERVICE: Uuid = uuid_from_u16(0x180F);
+const BATTERY_LEVEL: Uuid = uuid_from_u16(0x2A19);
+/// How often the level is read from devices that can't notify it.
+const POLL_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatteryLevel {
+    pub device_id: String,
+    /// Charge in percent.
+    pub level: u8,
+    /// Represents the time in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl BatteryLevel {
+    pub fn new(device_id: String, level: u8) -> Self {
+        Self {
+            device_id,
+            level,
+            timestamp: timestamp(),
+        }
+    }
+}
+
+fn battery_level_characteristic(peripheral: &Peripheral) -> Option<Characteristic> {
+    peripheral
+        .services()
+        .into_iter()
+        .find(|s| s.uuid == BATTERY_SERVICE)?
+        .characteristics
+        .into_iter()
+        .find(|c| c.uuid == BATTERY_LEVEL)
+}
+
+/// Reads the current level, if the device exposes the standard Battery Service.
+pub(super) async fn read_battery_level(peripheral: &Peripheral) -> Option<u8> {
+    let characteristic = battery_level_characteristic(peripheral)?;
+
+    match peripheral.read(&characteristic).await {
+        Ok(value) => value.first().copied(),
+        Err(err) => {
+            debug!("Failed to read battery level: {err:?}");
+            None
+        }
+    }
+}
+
+/// Calls `on_level` with every new battery level until the returned sender is
+/// dropped. Levels are notified when the device supports it, and polled otherwise.
+/// Returns `None` when the device has no Battery Service.
+pub(super) async fn watch_battery_level<F>(
+    peripheral: Peripheral,
+    notifications: &Notifications,
+    on_level: F,
+) -> Option<oneshot::Sender<()>>
+where
+    F: Fn(u8) + Send + 'static,
+{
+    let characteristic = battery_level_characteristic(&peripheral)?;
+    let (abort_sender, abort_receiver) = oneshot::channel();
+
+    if characteristic.properties.contains(CharPropFlags::NOTIFY) {
+        let mut levels = match notifications.subscribe(BATTERY_LEVEL).await {
+            Ok(levels) => levels,
+            Err(err) => {
+                warn!("Failed to subscribe to battery level: {err:?}");
+                return None;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    level = levels.next() => {
+                        let Some(level) = level else { break };
+                        if let Some(level) = level.value.first() {
+                            on_level(*level);
+                        }
+                    }
+                }
+            }
+        });
+    } else {
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+            let mut interval = tokio::time::interval(Duration::from_secs(POLL_INTERVAL_SECS));
+            // The level was just read at connect time
+            interval.tick().await;
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    _ = interval.tick() => {
+                        match peripheral.read(&characteristic).await {
+                            Ok(value) => {
+                                if let Some(level) = value.first() {
+                                    on_level(*level);
+                                }
+                            }
+                            Err(err) => debug!("Failed to poll battery level: {err:?}"),
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    Some(abort_sender)
+}
@@ -1,6 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// How a characteristic value reached the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueSource {
+    /// The value was explicitly read by a client.
+    Read,
+    /// The value was pushed by the peripheral without acknowledgement.
+    Notification,
+    /// The value was pushed by the peripheral and acknowledged by the adapter.
+    Indication,
+}
+
 #[derive(Debug)]
 pub struct CharacteristicValue {
     /// Represents the time in milliseconds since the Unix epoch.
     pub timestamp: u64,
     pub value: Vec<u8>,
+    pub source: ValueSource,
 }
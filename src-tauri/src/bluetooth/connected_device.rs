@@ -1,75 +1,89 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use btleplug::{
-    api::{Peripheral, Service},
+    api::{bleuuid::uuid_from_u16, Peripheral, Service},
     platform::Peripheral as PlatformPeripheral,
     Error,
 };
-use tracing::{info, warn};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, oneshot},
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
 
-use super::{discovery::discovered_device::DiscoveredDevice, notifications::Notifications};
+use super::{
+    battery::{read_battery_level, watch_battery_level},
+    cube::{
+        cube_event::{CubeEvent, CubeMove},
+        cube_state::CubeState,
+        move_tracker::{MoveTracker, Tracked},
+        start_driver, CubeCommand, CubeDriver, SmartCube,
+    },
+    device_data::device_info::DeviceInfo,
+    discovery::{device_kind::classify, discovered_device::DiscoveredDevice},
+    notifications::Notifications,
+    timer::{self, timer_event::TimerEvent, TimerDriver},
+    write_queue::WriteQueue,
+    BluetoothMessage,
+};
 
 const DISCOVER_RETRIES: u32 = 3;
 const DISCOVER_RETRY_DELAY_MS: u64 = 1_000;
 
+/// Service Changed characteristic of the Generic Attribute service (0x1801).
+const SERVICE_CHANGED_UUID: Uuid = uuid_from_u16(0x2A05);
+
 pub struct ConnectedDevice {
     pub peripheral: PlatformPeripheral,
     pub device: DiscoveredDevice,
     pub services: HashMap<String, Service>,
+    pub device_info: Option<DeviceInfo>,
+    pub battery_level: Option<u8>,
     pub client_count: usize,
     pub notifications: Notifications,
+    pub writes: WriteQueue,
+    /// Protocol driver, for supported smart cubes. Shared with the tasks
+    /// writing to the cube outside of the actor loop.
+    pub cube: Option<Arc<CubeDriver>>,
+    /// Tracked from what the cube driver decodes, unknown until the cube has
+    /// reported its facelets.
+    pub cube_state: Option<CubeState>,
+    /// Serials of the cube's moves, to notice lost ones.
+    pub cube_moves: MoveTracker,
+    /// Protocol driver, for supported smart timers.
+    pub timer: Option<TimerDriver>,
+    /// Dropping this stops the Service Changed watcher, if one is running.
+    service_changed_abort: Option<oneshot::Sender<()>>,
+    /// Dropping this stops the battery level watcher, if one is running.
+    battery_abort: Option<oneshot::Sender<()>>,
 }
 
 impl ConnectedDevice {
-    pub async fn start(peripheral: PlatformPeripheral, device_name: String) -> Result<Self, Error> {
+    pub async fn start(peripheral: PlatformPeripheral, device_id: String) -> Result<Self, Error> {
         let properties = peripheral
             .properties()
             .await?
             .ok_or(Error::DeviceNotFound)?;
-        let discovered_device: DiscoveredDevice = (device_name, properties).into();
-
-        let mut last_err = Error::DeviceNotFound;
-        for attempt in 1..=DISCOVER_RETRIES {
-            match peripheral.discover_services().await {
-                Ok(()) => {
-                    if attempt > 1 {
-                        info!("Service discovery succeeded on attempt {attempt}");
-                    }
-                    last_err = Error::DeviceNotFound; // won't be used
-                    break;
-                }
-                Err(e) => {
-                    warn!("Service discovery attempt {attempt}/{DISCOVER_RETRIES} failed: {e:?}");
-                    last_err = e;
-                    if attempt < DISCOVER_RETRIES {
-                        tokio::time::sleep(std::time::Duration::from_millis(
-                            DISCOVER_RETRY_DELAY_MS,
-                        ))
-                        .await;
-                    }
-                }
-            }
-        }
-        // Re-check by trying to read services; if the loop exhausted all retries the
-        // last error is propagated.
-        if peripheral.services().is_empty() {
-            return Err(last_err);
-        }
-
-        let services = peripheral.services();
+        let platform_id = peripheral.id().to_string();
+        let mut discovered_device: DiscoveredDevice = (device_id, platform_id, properties).into();
 
-        // services BTree as HashMap
-        let services: HashMap<_, _> = services
-            .into_iter()
-            .map(|s| (s.uuid.to_string(), s))
-            .collect();
+        let services = discover_services(&peripheral).await?;
+        reclassify(&mut discovered_device, &services);
+        let device_info = DeviceInfo::read(&peripheral).await;
+        let battery_level = read_battery_level(&peripheral).await;
 
         let notifications = Notifications::start(peripheral.clone());
+        let writes = WriteQueue::start(peripheral.clone());
         Ok(Self::new(
             peripheral,
             discovered_device,
             services,
+            device_info,
+            battery_level,
             notifications,
+            writes,
         ))
     }
 
@@ -77,17 +91,224 @@ impl ConnectedDevice {
         peripheral: PlatformPeripheral,
         device: DiscoveredDevice,
         services: HashMap<String, Service>,
+        device_info: Option<DeviceInfo>,
+        battery_level: Option<u8>,
         notifications: Notifications,
+        writes: WriteQueue,
     ) -> Self {
         Self {
             peripheral,
             device,
             services,
+            device_info,
+            battery_level,
             client_count: 0,
             notifications,
+            writes,
+            cube: None,
+            cube_state: None,
+            cube_moves: MoveTracker::default(),
+            timer: None,
+            service_changed_abort: None,
+            battery_abort: None,
+        }
+    }
+
+    /// Subscribes to Service Changed indications, calling `on_change` whenever the
+    /// peripheral reports that its GATT database was modified. Devices that do
+    /// not expose the characteristic are left alone.
+    pub async fn watch_service_changed<F>(&mut self, on_change: F)
+    where
+        F: Fn() + Send + 'static,
+    {
+        let has_service_changed = self
+            .peripheral
+            .characteristics()
+            .iter()
+            .any(|c| c.uuid == SERVICE_CHANGED_UUID);
+        if !has_service_changed {
+            return;
+        }
+
+        let mut indications = match self.notifications.subscribe(SERVICE_CHANGED_UUID).await {
+            Ok(indications) => indications,
+            Err(err) => {
+                warn!("Failed to subscribe to Service Changed indications: {err:?}");
+                return;
+            }
+        };
+
+        let (abort_sender, abort_receiver) = oneshot::channel();
+        self.service_changed_abort = Some(abort_sender);
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    indication = indications.next() => {
+                        if indication.is_none() {
+                            break;
+                        }
+                        on_change();
+                    }
+                }
+            }
+        });
+    }
+
+    /// Calls `on_level` with every battery level reported by the standard Battery
+    /// Service. Devices without the service are left alone.
+    pub async fn watch_battery_level<F>(&mut self, on_level: F)
+    where
+        F: Fn(u8) + Send + 'static,
+    {
+        self.battery_abort =
+            watch_battery_level(self.peripheral.clone(), &self.notifications, on_level).await;
+    }
+
+    /// Starts the protocol driver for the device, calling `on_event` with every
+    /// event it decodes. Devices without a driver are left alone.
+    pub async fn watch_cube<F>(&mut self, on_event: F)
+    where
+        F: Fn(CubeEvent) + Send + 'static,
+    {
+        self.cube = start_driver(
+            &self.device,
+            &self.peripheral,
+            &self.notifications,
+            on_event,
+        )
+        .await
+        .map(Arc::new);
+
+        // Cubes that can't report their facelets are assumed to start solved
+        if self
+            .cube
+            .as_ref()
+            .is_some_and(|cube| !cube.capabilities().state)
+        {
+            self.cube_state = Some(CubeState::solved());
+        }
+    }
+
+    /// Starts the protocol driver for the device, calling `on_event` with every
+    /// event it decodes. Devices without a driver are left alone.
+    pub async fn watch_timer<F>(&mut self, on_event: F)
+    where
+        F: Fn(TimerEvent) + Send + 'static,
+    {
+        self.timer = timer::start_driver(
+            &self.device,
+            &self.peripheral,
+            &self.notifications,
+            on_event,
+        )
+        .await;
+    }
+
+    /// Keeps the tracked state of the cube up to date. Facelets replace the
+    /// state, moves apply in the order of their serials: those after lost ones
+    /// are held back while the lost ones are recovered from the cube's history.
+    /// Returns the events to pass on. A failed history request is reported with
+    /// a `CubeHistoryFailed` message on `self_tx`.
+    pub(super) fn track_cube_event(
+        &mut self,
+        event: CubeEvent,
+        self_tx: &UnboundedSender<BluetoothMessage>,
+    ) -> Vec<CubeEvent> {
+        match event {
+            CubeEvent::Move(cube_move) => match self.cube_moves.track(cube_move) {
+                Tracked::Moves(moves) => self.apply_moves(moves),
+                Tracked::Held => vec![],
+                Tracked::Gap { serial, missing } => {
+                    self.request_move_history(serial, missing, self_tx.clone());
+                    vec![]
+                }
+            },
+            CubeEvent::MoveHistory(history) => match self.cube_moves.recover(&history) {
+                Some(moves) => self.apply_moves(moves),
+                None => self.desync_cube(),
+            },
+            CubeEvent::Facelets { serial, facelets } => {
+                match CubeState::from_facelets(&facelets) {
+                    Some(state) => self.cube_state = Some(state),
+                    None => warn!("{} reported unsolvable facelets {facelets}", self.device.id),
+                }
+                let newer = self.cube_moves.resync(serial);
+
+                let mut events = vec![CubeEvent::Facelets { serial, facelets }];
+                events.extend(self.apply_moves(newer));
+                events
+            }
+            event => vec![event],
         }
     }
 
+    /// Asks the cube to replay the `missing` moves before the one numbered
+    /// `serial`, without waiting for the write.
+    fn request_move_history(
+        &self,
+        serial: u16,
+        missing: u8,
+        self_tx: UnboundedSender<BluetoothMessage>,
+    ) {
+        let Some(cube) = self.cube.clone() else {
+            return;
+        };
+        let device_id = self.device.id.clone();
+
+        tokio::spawn(async move {
+            // The held move is asked for too, to anchor the window
+            if let Err(err) = cube.request_move_history(serial, missing + 1).await {
+                debug!("Can't recover {missing} moves before {serial}: {err:?}");
+                let _ = self_tx.send(BluetoothMessage::CubeHistoryFailed(device_id));
+            }
+        });
+    }
+
+    /// Gives up on lost moves. The state is unknown until the cube reports its
+    /// facelets, which it is asked for without waiting for the write.
+    pub fn desync_cube(&mut self) -> Vec<CubeEvent> {
+        let (desync, held) = self.cube_moves.desync();
+        warn!("Lost moves of {}: {desync:?}", self.device.id);
+        self.cube_state = None;
+
+        if let Some(cube) = self.cube.clone() {
+            let device_id = self.device.id.clone();
+            tokio::spawn(async move {
+                if let Err(err) = cube.send(CubeCommand::RequestFacelets).await {
+                    warn!("Failed to request facelets of {device_id}: {err:?}");
+                }
+            });
+        }
+
+        std::iter::once(desync)
+            .chain(held.into_iter().map(CubeEvent::Move))
+            .collect()
+    }
+
+    fn apply_moves(&mut self, moves: Vec<CubeMove>) -> Vec<CubeEvent> {
+        if let Some(state) = &mut self.cube_state {
+            for cube_move in &moves {
+                state.apply(cube_move);
+            }
+        }
+
+        moves.into_iter().map(CubeEvent::Move).collect()
+    }
+
+    /// Rediscovers the peripheral's services and rebuilds the services map. The
+    /// device information is read again, as a firmware switch may have changed it.
+    pub async fn refresh_services(&mut self) -> Result<(), Error> {
+        self.services = discover_services(&self.peripheral).await?;
+        reclassify(&mut self.device, &self.services);
+        self.device_info = DeviceInfo::read(&self.peripheral).await;
+
+        Ok(())
+    }
+
     pub fn add_client(&mut self) {
         self.client_count += 1;
     }
@@ -101,4 +322,61 @@ impl ConnectedDevice {
     pub fn has_no_clients(&self) -> bool {
         self.client_count == 0
     }
+
+    /// Stops the per-device actors once the device is gone.
+    pub async fn stop(&self) {
+        self.notifications.stop().await;
+        self.writes.stop().await;
+    }
+}
+
+/// Classifies the device again with the full list of services, which tells
+/// protocol generations apart where the advertisement couldn't.
+fn reclassify(device: &mut DiscoveredDevice, services: &HashMap<String, Service>) {
+    let uuids: Vec<_> = services.values().map(|service| service.uuid).collect();
+    let manufacturer_data = device.manufacturer_data.clone().unwrap_or_default();
+
+    if let Some(kind) = classify(device.name.as_deref(), &manufacturer_data, &uuids) {
+        device.device_kind = Some(kind);
+    }
+}
+
+async fn discover_services(
+    peripheral: &PlatformPeripheral,
+) -> Result<HashMap<String, Service>, Error> {
+    let mut last_err = Error::DeviceNotFound;
+    for attempt in 1..=DISCOVER_RETRIES {
+        match peripheral.discover_services().await {
+            Ok(()) => {
+                if attempt > 1 {
+                    info!("Service discovery succeeded on attempt {attempt}");
+                }
+                last_err = Error::DeviceNotFound; // won't be used
+                break;
+            }
+            Err(e) => {
+                warn!("Service discovery attempt {attempt}/{DISCOVER_RETRIES} failed: {e:?}");
+                last_err = e;
+                if attempt < DISCOVER_RETRIES {
+                    tokio::time::sleep(std::time::Duration::from_millis(DISCOVER_RETRY_DELAY_MS))
+                        .await;
+                }
+            }
+        }
+    }
+    // Re-check by trying to read services; if the loop exhausted all retries the
+    // last error is propagated.
+    if peripheral.services().is_empty() {
+        return Err(last_err);
+    }
+
+    let services = peripheral.services();
+
+    // services BTree as HashMap
+    let services: HashMap<_, _> = services
+        .into_iter()
+        .map(|s| (s.uuid.to_string(), s))
+        .collect();
+
+    Ok(services)
 }
@@ -0,0 +1,241 @@
+//! Drivers decoding the proprietary protocols of smart cubes into `CubeEvent`s,
+//! so that clients don't have to. Raw characteristic access keeps working next
+//! to a running driver.
+
+use btleplug::{
+    api::{BDAddr, CharPropFlags, Characteristic, Peripheral as _, WriteType},
+    platform::Peripheral,
+    Error,
+};
+use cube_event::CubeEvent;
+use gan::{GanDriver, Generation};
+use giiker::GiikerDriver;
+use moyu32::MoYu32Driver;
+use qiyi::QiYiDriver;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{discovery::discovered_device::DiscoveredDevice, notifications::Notifications};
+
+mod bits;
+pub mod cube_event;
+pub mod cube_state;
+pub mod facelets;
+mod gan;
+mod gan_cipher;
+mod gan_gen2;
+mod gan_gen3;
+mod gan_gen4;
+mod giiker;
+pub mod move_tracker;
+mod moyu32;
+pub(super) mod qiyi;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CubeCommand {
+    /// Answered with a `cube-state` event.
+    RequestFacelets,
+    /// Answered with a `cube-battery` event.
+    RequestBattery,
+    /// Answered with a `cube-hardware` event.
+    RequestHardware,
+    /// Makes the cube consider its current state solved.
+    ResetSolved,
+}
+
+/// What a cube supports beyond reporting its moves, so that clients can treat
+/// all brands alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CubeCapabilities {
+    /// Reports its facelets on request.
+    pub state: bool,
+    /// Reports its battery level on request.
+    pub battery: bool,
+    /// Reports its hardware and software versions on request.
+    pub hardware: bool,
+    /// Can be told that its current state is solved.
+    pub reset: bool,
+    /// Reports its orientation.
+    pub gyro: bool,
+    /// Numbers its moves, so that lost ones can be noticed.
+    pub move_serials: bool,
+    /// Replays recent moves on request, so that lost ones can be recovered.
+    pub move_history: bool,
+}
+
+impl CubeCapabilities {
+    pub fn supports(&self, command: CubeCommand) -> bool {
+        match command {
+            CubeCommand::RequestFacelets => self.state,
+            CubeCommand::RequestBattery => self.battery,
+            CubeCommand::RequestHardware => self.hardware,
+            CubeCommand::ResetSolved => self.reset,
+        }
+    }
+}
+
+/// The brand-neutral interface of cube drivers.
+pub(crate) trait SmartCube {
+    fn capabilities(&self) -> CubeCapabilities;
+
+    /// Sends a command the cube answers with events.
+    async fn send(&self, command: CubeCommand) -> Result<(), Error>;
+
+    /// Asks the cube to replay `count` moves up to the one numbered `serial`,
+    /// answered with a `CubeEvent::MoveHistory`.
+    async fn request_move_history(&self, _serial: u16, _count: u8) -> Result<(), Error> {
+        Err(Error::NotSupported(
+            "The cube doesn't keep a move history".to_string(),
+        ))
+    }
+}
+
+pub(crate) enum CubeDriver {
+    Gan(GanDriver),
+    Giiker(GiikerDriver),
+    MoYu32(MoYu32Driver),
+    QiYi(QiYiDriver),
+}
+
+impl SmartCube for CubeDriver {
+    fn capabilities(&self) -> CubeCapabilities {
+        match self {
+            Self::Gan(driver) => driver.capabilities(),
+            Self::Giiker(driver) => driver.capabilities(),
+            Self::MoYu32(driver) => driver.capabilities(),
+            Self::QiYi(driver) => driver.capabilities(),
+        }
+    }
+
+    /// Fails with `Error::NotSupported` for commands the cube lacks the
+    /// capability for.
+    async fn send(&self, command: CubeCommand) -> Result<(), Error> {
+        if !self.capabilities().supports(command) {
+            return Err(Error::NotSupported(format!(
+                "The cube doesn't support {command:?}"
+            )));
+        }
+
+        match self {
+            Self::Gan(driver) => driver.send(command).await,
+            Self::Giiker(driver) => driver.send(command).await,
+            Self::MoYu32(driver) => driver.send(command).await,
+            Self::QiYi(driver) => driver.send(command).await,
+        }
+    }
+
+    async fn request_move_history(&self, serial: u16, count: u8) -> Result<(), Error> {
+        match self {
+            Self::Gan(driver) => driver.request_move_history(serial, count).await,
+            Self::Giiker(driver) => driver.request_move_history(serial, count).await,
+            Self::MoYu32(driver) => driver.request_move_history(serial, count).await,
+            Self::QiYi(driver) => driver.request_move_history(serial, count).await,
+        }
+    }
+}
+
+/// Starts the driver for the protocol `device` was classified with, if there is
+/// one. Returns `None` for other devices.
+pub(super) async fn start_driver<F>(
+    device: &DiscoveredDevice,
+    peripheral: &Peripheral,
+    notifications: &Notifications,
+    on_event: F,
+) -> Option<CubeDriver>
+where
+    F: Fn(CubeEvent) + Send + 'static,
+{
+    let protocol = device.device_kind.as_ref()?.protocol.as_deref()?;
+
+    let result = match protocol {
+        "giiker" => GiikerDriver::start(peripheral.clone(), notifications, on_event)
+            .await
+            .map(CubeDriver::Giiker),
+        "moyu-my32" => MoYu32Driver::start(
+            peripheral.clone(),
+            notifications,
+            address(device)?,
+            on_event,
+        )
+        .await
+        .map(CubeDriver::MoYu32),
+        "qiyi" => QiYiDriver::start(
+            peripheral.clone(),
+            notifications,
+            address(device)?,
+            on_event,
+        )
+        .await
+        .map(CubeDriver::QiYi),
+        _ => {
+            let generation = match protocol {
+                "gan-gen2" => Generation::Gen2,
+                "gan-gen3" => Generation::Gen3,
+                "gan-gen4" => Generation::Gen4,
+                _ => return None,
+            };
+            GanDriver::start(
+                generation,
+                peripheral.clone(),
+                notifications,
+                device.name.as_deref(),
+                address(device)?,
+                on_event,
+            )
+            .await
+            .map(CubeDriver::Gan)
+        }
+    };
+
+    match result {
+        Ok(driver) => {
+            info!("Started {protocol} driver for {}", device.id);
+            Some(driver)
+        }
+        Err(err) => {
+            warn!(
+                "Failed to start {protocol} driver for {}: {err:?}",
+                device.id
+            );
+            None
+        }
+    }
+}
+
+/// Encrypts a packet with `encrypt` and writes it, with a response if the
+/// characteristic supports one. Fails if `encrypt` can't handle the packet.
+pub(super) async fn write_encrypted<F>(
+    peripheral: &Peripheral,
+    characteristic: &Characteristic,
+    packet: &[u8],
+    encrypt: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(&[u8]) -> Option<Vec<u8>>,
+{
+    let packet = encrypt(packet)
+        .ok_or_else(|| Error::Other(format!("Can't encrypt packet {packet:?}").into()))?;
+    let write_type = if characteristic.properties.contains(CharPropFlags::WRITE) {
+        WriteType::WithResponse
+    } else {
+        WriteType::WithoutResponse
+    };
+
+    peripheral.write(characteristic, &packet, write_type).await
+}
+
+/// The device's MAC address, which some protocols derive their keys from.
+pub(super) fn address(device: &DiscoveredDevice) -> Option<[u8; 6]> {
+    let address = device
+        .address
+        .iter()
+        .chain(&device.derived_address)
+        .filter_map(|address| address.parse::<BDAddr>().ok())
+        .find(|address| *address != BDAddr::default());
+
+    if address.is_none() {
+        warn!("No address known for {}, can't derive its keys", device.id);
+    }
+
+    address.map(BDAddr::into_inner)
+}
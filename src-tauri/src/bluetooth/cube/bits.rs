@@ -0,0 +1,32 @@
+/// Reads bit fields from a packet, most significant bit first, the way cube
+/// protocols lay them out.
+pub(super) struct Bits<'a>(pub &'a [u8]);
+
+impl Bits<'_> {
+    /// Returns the `length` bits (at most 32) starting at bit `start`. Bits past
+    /// the end of the packet read as zero.
+    pub fn get(&self, start: usize, length: usize) -> u32 {
+        (start..start + length).fold(0, |value, bit| {
+            let byte = self.0.get(bit / 8).copied().unwrap_or_default();
+            (value << 1) | u32::from((byte >> (7 - bit % 8)) & 1)
+        })
+    }
+
+    /// Reads `length` bytes starting at bit `start` as text, dropping padding.
+    pub fn text(&self, start: usize, length: usize) -> String {
+        (0..length)
+            .map(|i| self.get(start + i * 8, 8) as u8)
+            .filter(|byte| *byte != 0)
+            .map(char::from)
+            .collect()
+    }
+
+    /// Like `get`, for fields stored least significant byte first.
+    pub fn get_le(&self, start: usize, length: usize) -> u32 {
+        let bytes = length.div_ceil(8);
+        (0..bytes).fold(0, |value, i| {
+            let bits = (length - i * 8).min(8);
+            value | (self.get(start + i * 8, bits) << (i * 8))
+        })
+    }
+}
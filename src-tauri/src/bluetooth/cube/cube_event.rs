@@ -0,0 +1,150 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bluetooth::timestamp::timestamp;
+
+/// Something a smart cube reported, decoded from its proprietary protocol.
+#[derive(Debug, Clone)]
+pub enum CubeEvent {
+    Move(CubeMove),
+    Facelets {
+        serial: Option<u16>,
+        /// Sticker colors in Kociemba order (U1..U9, R1..R9, F, D, L, B), each
+        /// named after the face of the same color.
+        facelets: String,
+    },
+    Gyro(Gyro),
+    /// Charge in percent.
+    Battery(u8),
+    Hardware(HardwareInfo),
+    /// Recent moves the cube replayed on request, newest first. Only used to
+    /// recover lost moves, not passed on to clients.
+    MoveHistory(Vec<CubeMove>),
+    /// Moves were lost and couldn't be recovered. The state is unknown until
+    /// the cube reports its facelets again.
+    Desync {
+        /// Serial of the first lost move.
+        expected_serial: u16,
+        /// Serial of the move that arrived instead.
+        serial: u16,
+    },
+}
+
+/// A cube event together with the device that reported it.
+#[derive(Debug, Clone)]
+pub struct DeviceCubeEvent {
+    pub device_id: String,
+    /// Represents the time in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub event: CubeEvent,
+}
+
+impl DeviceCubeEvent {
+    pub fn new(device_id: String, event: CubeEvent) -> Self {
+        Self {
+            device_id,
+            timestamp: timestamp(),
+            event,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Face {
+    U,
+    R,
+    F,
+    D,
+    L,
+    B,
+}
+
+impl Face {
+    /// Faces in the order most protocols number them.
+    pub const ALL: [Self; 6] = [Self::U, Self::R, Self::F, Self::D, Self::L, Self::B];
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(usize::try_from(index).ok()?).copied()
+    }
+}
+
+impl Display for Face {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CubeMove {
+    pub face: Face,
+    pub direction: Direction,
+    /// The move in WCA notation, e.g. `R'`.
+    pub notation: String,
+    /// Move counter of cubes that number their moves.
+    pub serial: Option<u16>,
+    /// Milliseconds on the cube's own clock, for cubes that time their moves.
+    pub cube_timestamp: Option<u64>,
+}
+
+impl CubeMove {
+    pub fn new(
+        face: Face,
+        direction: Direction,
+        serial: Option<u16>,
+        cube_timestamp: Option<u64>,
+    ) -> Self {
+        let notation = match direction {
+            Direction::Clockwise => face.to_string(),
+            Direction::CounterClockwise => format!("{face}'"),
+        };
+
+        Self {
+            face,
+            direction,
+            notation,
+            serial,
+            cube_timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gyro {
+    /// Orientation of the cube.
+    pub quaternion: Quaternion,
+    /// Angular velocity, in device-specific units.
+    pub velocity: Option<Vector>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Vector {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// What the cube tells about itself. Cubes report different subsets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareInfo {
+    pub hardware_name: Option<String>,
+    pub hardware_version: Option<String>,
+    pub software_version: Option<String>,
+    pub product_date: Option<String>,
+    pub gyro_supported: Option<bool>,
+}
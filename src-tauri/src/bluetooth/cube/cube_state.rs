@@ -0,0 +1,160 @@
+//! State of a 3x3 cube as tracked by the proxy, from the moves and facelets its
+//! driver decodes, so that clients don't each have to keep their own.
+
+use super::{
+    cube_event::{CubeMove, Direction, Face},
+    facelets::CubieState,
+};
+
+/// Clockwise quarter turns of U, R, F, D, L and B, as the cubies each position
+/// is replaced by.
+const FACE_TURNS: [CubieState; 6] = [
+    CubieState {
+        corner_permutation: [3, 0, 1, 2, 4, 5, 6, 7],
+        corner_orientation: [0; 8],
+        edge_permutation: [3, 0, 1, 2, 4, 5, 6, 7, 8, 9, 10, 11],
+        edge_orientation: [0; 12],
+    },
+    CubieState {
+        corner_permutation: [4, 1, 2, 0, 7, 5, 6, 3],
+        corner_orientation: [2, 0, 0, 1, 1, 0, 0, 2],
+        edge_permutation: [8, 1, 2, 3, 11, 5, 6, 7, 4, 9, 10, 0],
+        edge_orientation: [0; 12],
+    },
+    CubieState {
+        corner_permutation: [1, 5, 2, 3, 0, 4, 6, 7],
+        corner_orientation: [1, 2, 0, 0, 2, 1, 0, 0],
+        edge_permutation: [0, 9, 2, 3, 4, 8, 6, 7, 1, 5, 10, 11],
+        edge_orientation: [0, 1, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0],
+    },
+    CubieState {
+        corner_permutation: [0, 1, 2, 3, 5, 6, 7, 4],
+        corner_orientation: [0; 8],
+        edge_permutation: [0, 1, 2, 3, 5, 6, 7, 4, 8, 9, 10, 11],
+        edge_orientation: [0; 12],
+    },
+    CubieState {
+        corner_permutation: [0, 2, 6, 3, 4, 1, 5, 7],
+        corner_orientation: [0, 1, 2, 0, 0, 2, 1, 0],
+        edge_permutation: [0, 1, 10, 3, 4, 5, 9, 7, 8, 2, 6, 11],
+        edge_orientation: [0; 12],
+    },
+    CubieState {
+        corner_permutation: [0, 1, 3, 7, 4, 5, 2, 6],
+        corner_orientation: [0, 0, 1, 2, 0, 0, 2, 1],
+        edge_permutation: [0, 1, 2, 11, 4, 5, 6, 10, 8, 9, 3, 7],
+        edge_orientation: [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1],
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CubeState {
+    cubies: CubieState,
+}
+
+impl Default for CubeState {
+    fn default() -> Self {
+        Self::solved()
+    }
+}
+
+impl CubeState {
+    pub fn solved() -> Self {
+        Self {
+            cubies: CubieState::SOLVED,
+        }
+    }
+
+    /// Parses a Kociemba facelet string. Returns `None` for unsolvable states.
+    pub fn from_facelets(facelets: &str) -> Option<Self> {
+        CubieState::from_facelets(facelets).map(|cubies| Self { cubies })
+    }
+
+    pub fn cubies(&self) -> &CubieState {
+        &self.cubies
+    }
+
+    /// The state as a Kociemba facelet string.
+    pub fn facelets(&self) -> String {
+        self.cubies
+            .to_facelets()
+            .expect("Tracked states only have valid permutations")
+    }
+
+    /// Turns `face` clockwise by `quarter_turns`.
+    pub fn turn(&mut self, face: Face, quarter_turns: u8) {
+        let index = Face::ALL
+            .iter()
+            .position(|f| *f == face)
+            .unwrap_or_default();
+        for _ in 0..quarter_turns % 4 {
+            self.cubies = self.cubies.multiply(&FACE_TURNS[index]);
+        }
+    }
+
+    pub fn apply(&mut self, cube_move: &CubeMove) {
+        let quarter_turns = match cube_move.direction {
+            Direction::Clockwise => 1,
+            Direction::CounterClockwise => 3,
+        };
+        self.turn(cube_move.face, quarter_turns);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R_FACELETS: &str = "UUFUUFUUFRRRRRRRRRFFDFFDFFDDDBDDBDDBLLLLLLLLLUBBUBBUBB";
+
+    #[test]
+    fn turns_faces() {
+        let mut state = CubeState::solved();
+
+        state.turn(Face::R, 1);
+
+        assert_eq!(state.facelets(), R_FACELETS);
+    }
+
+    #[test]
+    fn returns_to_solved_after_four_quarter_turns() {
+        for face in Face::ALL {
+            let mut state = CubeState::solved();
+
+            state.turn(face, 1);
+            assert_ne!(state, CubeState::solved());
+            state.turn(face, 3);
+
+            assert_eq!(state, CubeState::solved());
+        }
+    }
+
+    #[test]
+    fn returns_to_solved_after_six_sexy_moves() {
+        let mut state = CubeState::solved();
+
+        for i in 0..6 {
+            assert_eq!(state == CubeState::solved(), i == 0);
+            state.turn(Face::R, 1);
+            state.turn(Face::U, 1);
+            state.turn(Face::R, 3);
+            state.turn(Face::U, 3);
+        }
+
+        assert_eq!(state, CubeState::solved());
+    }
+
+    #[test]
+    fn applies_moves() {
+        let mut state = CubeState::from_facelets(R_FACELETS).unwrap();
+
+        state.apply(&CubeMove::new(
+            Face::R,
+            Direction::CounterClockwise,
+            None,
+            None,
+        ));
+
+        assert_eq!(state, CubeState::solved());
+    }
+}
@@ -0,0 +1,260 @@
+//! Conversion between corner and edge permutations and orientations, the way
+//! most cubes report their state, and Kociemba facelet strings.
+
+use serde::{Deserialize, Serialize};
+
+const FACES: &[u8; 6] = b"URFDLB";
+
+/// Facelet indices of each corner position, URF, UFL, ULB, UBR, DFR, DLF, DBL, DRB.
+const CORNER_FACELETS: [[usize; 3]; 8] = [
+    [8, 9, 20],
+    [6, 18, 38],
+    [0, 36, 47],
+    [2, 45, 11],
+    [29, 26, 15],
+    [27, 44, 24],
+    [33, 53, 42],
+    [35, 17, 51],
+];
+
+/// Facelet indices of each edge position, UR, UF, UL, UB, DR, DF, DL, DB, FR, FL,
+/// BL, BR.
+const EDGE_FACELETS: [[usize; 2]; 12] = [
+    [5, 10],
+    [7, 19],
+    [3, 37],
+    [1, 46],
+    [32, 16],
+    [28, 25],
+    [30, 43],
+    [34, 52],
+    [23, 12],
+    [21, 41],
+    [50, 39],
+    [48, 14],
+];
+
+/// Corner and edge state, in the usual Kociemba numbering unless converted with
+/// other facelet tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CubieState {
+    pub corner_permutation: [u8; 8],
+    pub corner_orientation: [u8; 8],
+    pub edge_permutation: [u8; 12],
+    pub edge_orientation: [u8; 12],
+}
+
+impl CubieState {
+    pub const SOLVED: Self = Self {
+        corner_permutation: [0, 1, 2, 3, 4, 5, 6, 7],
+        corner_orientation: [0; 8],
+        edge_permutation: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        edge_orientation: [0; 12],
+    };
+
+    /// Parses a Kociemba facelet string. Returns `None` unless it describes a
+    /// state reachable by turning the faces.
+    pub fn from_facelets(facelets: &str) -> Option<Self> {
+        let facelets = facelets.as_bytes();
+        if facelets.len() != 54 || (0..6).any(|face| facelets[face * 9 + 4] != FACES[face]) {
+            return None;
+        }
+        let mut state = Self::default();
+
+        for (i, position) in CORNER_FACELETS.iter().enumerate() {
+            // Twisted by where its U or D sticker is
+            let orientation = (0..3).find(|&p| matches!(facelets[position[p]], b'U' | b'D'))?;
+            let colors = [1, 2].map(|p| facelets[position[(p + orientation) % 3]]);
+            state.corner_permutation[i] = CORNER_FACELETS
+                .iter()
+                .position(|corner| [corner[1], corner[2]].map(|f| FACES[f / 9]) == colors)?
+                as u8;
+            state.corner_orientation[i] = orientation as u8;
+        }
+        for (i, position) in EDGE_FACELETS.iter().enumerate() {
+            let colors = position.map(|f| facelets[f]);
+            let (edge, orientation) = EDGE_FACELETS.iter().enumerate().find_map(|(e, edge)| {
+                let edge_colors = edge.map(|f| FACES[f / 9]);
+                if edge_colors == colors {
+                    Some((e, 0))
+                } else if edge_colors == [colors[1], colors[0]] {
+                    Some((e, 1))
+                } else {
+                    None
+                }
+            })?;
+            state.edge_permutation[i] = edge as u8;
+            state.edge_orientation[i] = orientation;
+        }
+
+        state.is_solvable().then_some(state)
+    }
+
+    /// Every cubie is there once, twists and flips cancel out, and corners and
+    /// edges are permuted with the same parity.
+    pub fn is_solvable(&self) -> bool {
+        let is_permutation = |permutation: &[u8]| {
+            (0..permutation.len()).all(|cubie| permutation.contains(&(cubie as u8)))
+        };
+
+        is_permutation(&self.corner_permutation)
+            && is_permutation(&self.edge_permutation)
+            && self.corner_orientation.iter().all(|&o| o < 3)
+            && self.edge_orientation.iter().all(|&o| o < 2)
+            && self.corner_orientation.iter().sum::<u8>() % 3 == 0
+            && self.edge_orientation.iter().sum::<u8>() % 2 == 0
+            && parity(&self.corner_permutation) == parity(&self.edge_permutation)
+    }
+
+    /// The state reached by applying `other` to this one.
+    pub fn multiply(&self, other: &Self) -> Self {
+        let mut state = Self::default();
+
+        for (i, &corner) in other.corner_permutation.iter().enumerate() {
+            let corner = usize::from(corner);
+            state.corner_permutation[i] = self.corner_permutation[corner];
+            state.corner_orientation[i] =
+                (self.corner_orientation[corner] + other.corner_orientation[i]) % 3;
+        }
+        for (i, &edge) in other.edge_permutation.iter().enumerate() {
+            let edge = usize::from(edge);
+            state.edge_permutation[i] = self.edge_permutation[edge];
+            state.edge_orientation[i] =
+                (self.edge_orientation[edge] + other.edge_orientation[i]) % 2;
+        }
+
+        state
+    }
+
+    /// Fills in the last corner and edge from the others, for cubes that leave
+    /// them out. Returns `None` if the others are inconsistent.
+    pub fn complete(&mut self) -> Option<()> {
+        let corners: u8 = self.corner_permutation[..7].iter().sum();
+        let twist: u8 = self.corner_orientation[..7].iter().sum();
+        let edges: u8 = self.edge_permutation[..11].iter().sum();
+        let flip: u8 = self.edge_orientation[..11].iter().sum();
+
+        self.corner_permutation[7] = 28u8.checked_sub(corners)?;
+        self.corner_orientation[7] = (3 - twist % 3) % 3;
+        self.edge_permutation[11] = 66u8.checked_sub(edges)?;
+        self.edge_orientation[11] = (2 - flip % 2) % 2;
+
+        Some(())
+    }
+
+    /// Returns `None` if a permutation entry is out of range.
+    pub fn to_facelets(&self) -> Option<String> {
+        self.to_facelets_with(&CORNER_FACELETS, &EDGE_FACELETS)
+    }
+
+    /// Like `to_facelets`, for cubes that number their corner and edge
+    /// positions, or the facelets within them, differently.
+    pub fn to_facelets_with(
+        &self,
+        corner_facelets: &[[usize; 3]; 8],
+        edge_facelets: &[[usize; 2]; 12],
+    ) -> Option<String> {
+        let mut facelets: Vec<u8> = (0..54).map(|i| FACES[i / 9]).collect();
+
+        for (i, &corner) in self.corner_permutation.iter().enumerate() {
+            let source = corner_facelets.get(usize::from(corner))?;
+            let orientation = usize::from(self.corner_orientation[i]);
+            for (p, &facelet) in source.iter().enumerate() {
+                facelets[corner_facelets[i][(p + orientation) % 3]] = FACES[facelet / 9];
+            }
+        }
+        for (i, &edge) in self.edge_permutation.iter().enumerate() {
+            let source = edge_facelets.get(usize::from(edge))?;
+            let orientation = usize::from(self.edge_orientation[i]);
+            for (p, &facelet) in source.iter().enumerate() {
+                facelets[edge_facelets[i][(p + orientation) % 2]] = FACES[facelet / 9];
+            }
+        }
+
+        String::from_utf8(facelets).ok()
+    }
+}
+
+/// Whether the permutation is odd, by counting inversions.
+fn parity(permutation: &[u8]) -> bool {
+    let inversions = (0..permutation.len())
+        .flat_map(|i| (i + 1..permutation.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| permutation[i] > permutation[j])
+        .count();
+
+    inversions % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// After R U F' D2 L B' U R2 F D'.
+    const SCRAMBLED: CubieState = CubieState {
+        corner_permutation: [7, 0, 1, 6, 5, 2, 3, 4],
+        corner_orientation: [0, 1, 1, 0, 0, 0, 2, 2],
+        edge_permutation: [6, 1, 4, 10, 0, 11, 2, 8, 3, 7, 9, 5],
+        edge_orientation: [0, 1, 1, 0, 1, 1, 0, 1, 1, 1, 0, 1],
+    };
+    const SCRAMBLED_FACELETS: &str = "LBDRUDFFDRLLURDFURUUBBFBBBLURDUDRBRFUDRLLDULLBLFFBFDFR";
+    const SOLVED_FACELETS: &str = "UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB";
+
+    #[test]
+    fn converts_to_facelets() {
+        assert_eq!(
+            CubieState::SOLVED.to_facelets().as_deref(),
+            Some(SOLVED_FACELETS)
+        );
+        assert_eq!(SCRAMBLED.to_facelets().as_deref(), Some(SCRAMBLED_FACELETS));
+    }
+
+    #[test]
+    fn round_trips_facelets() {
+        let state = CubieState::from_facelets(SCRAMBLED_FACELETS).unwrap();
+
+        assert_eq!(state, SCRAMBLED);
+        assert_eq!(state.to_facelets().as_deref(), Some(SCRAMBLED_FACELETS));
+    }
+
+    #[test]
+    fn rejects_unsolvable_facelets() {
+        // The URF corner twisted in place
+        let mut twisted = SOLVED_FACELETS.as_bytes().to_vec();
+        twisted[8] = b'R';
+        twisted[9] = b'F';
+        twisted[20] = b'U';
+
+        assert_eq!(
+            CubieState::from_facelets(std::str::from_utf8(&twisted).unwrap()),
+            None
+        );
+        assert_eq!(CubieState::from_facelets(&SOLVED_FACELETS[1..]), None);
+    }
+
+    #[test]
+    fn completes_the_last_corner_and_edge() {
+        let mut state = SCRAMBLED;
+        state.corner_permutation[7] = 0;
+        state.corner_orientation[7] = 0;
+        state.edge_permutation[11] = 0;
+        state.edge_orientation[11] = 0;
+
+        assert_eq!(state.complete(), Some(()));
+
+        assert_eq!(state, SCRAMBLED);
+    }
+
+    #[test]
+    fn rejects_inconsistent_states_to_complete() {
+        let mut state = SCRAMBLED;
+        state.corner_permutation[..7].copy_from_slice(&[7; 7]);
+
+        assert_eq!(state.complete(), None);
+    }
+
+    #[test]
+    fn multiplies_with_solved_as_identity() {
+        assert_eq!(SCRAMBLED.multiply(&CubieState::SOLVED), SCRAMBLED);
+        assert_eq!(CubieState::SOLVED.multiply(&SCRAMBLED), SCRAMBLED);
+    }
+}
@@ -0,0 +1,164 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+
+/// Base key and IV of GAN cubes.
+pub(super) const GAN_KEY: ([u8; 16], [u8; 16]) = (
+    [
+        0x01, 0x02, 0x42, 0x28, 0x31, 0x91, 0x16, 0x07, 0x20, 0x05, 0x18, 0x54, 0x42, 0x11, 0x12,
+        0x53,
+    ],
+    [
+        0x11, 0x03, 0x32, 0x28, 0x21, 0x01, 0x76, 0x27, 0x20, 0x95, 0x78, 0x14, 0x32, 0x12, 0x02,
+        0x43,
+    ],
+);
+
+/// Base key and IV of MoYu AI 2023 cubes, which speak the GAN Gen2 protocol.
+pub(super) const MOYU_KEY: ([u8; 16], [u8; 16]) = (
+    [
+        0x05, 0x12, 0x02, 0x45, 0x02, 0x01, 0x29, 0x56, 0x12, 0x78, 0x12, 0x76, 0x81, 0x01, 0x08,
+        0x03,
+    ],
+    [
+        0x01, 0x44, 0x28, 0x06, 0x86, 0x21, 0x22, 0x28, 0x51, 0x05, 0x08, 0x31, 0x82, 0x02, 0x21,
+        0x06,
+    ],
+);
+
+/// Base key and IV of MoYu WeiLong AI (MY32) cubes, which have their own protocol
+/// but encrypt it the GAN way.
+pub(super) const MOYU32_KEY: ([u8; 16], [u8; 16]) = (
+    [
+        0x15, 0x77, 0x3A, 0x5C, 0x67, 0x0E, 0x2D, 0x1F, 0x17, 0x67, 0x2A, 0x13, 0x9B, 0x67, 0x52,
+        0x57,
+    ],
+    [
+        0x11, 0x23, 0x26, 0x25, 0x86, 0x2A, 0x2C, 0x3B, 0x55, 0x06, 0x7F, 0x31, 0x7E, 0x67, 0x21,
+        0x57,
+    ],
+);
+
+const BLOCK: usize = 16;
+
+/// AES-128 as used by GAN and MoYu cubes: the first and the last 16 bytes of a
+/// packet are each encrypted as a single CBC block, with a key and IV salted
+/// with the device's MAC address.
+pub(super) struct GanCipher {
+    cipher: Aes128,
+    iv: [u8; BLOCK],
+}
+
+impl GanCipher {
+    pub fn new((mut key, mut iv): ([u8; 16], [u8; 16]), address: [u8; 6]) -> Self {
+        // The salt is the address, least significant byte first
+        for (i, salt) in address.iter().rev().enumerate() {
+            key[i] = ((u16::from(key[i]) + u16::from(*salt)) % 0xFF) as u8;
+            iv[i] = ((u16::from(iv[i]) + u16::from(*salt)) % 0xFF) as u8;
+        }
+
+        Self {
+            cipher: Aes128::new(&GenericArray::from(key)),
+            iv,
+        }
+    }
+
+    /// Returns `None` for packets shorter than a block.
+    pub fn encrypt(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let mut packet = packet.to_vec();
+        let len = packet.len();
+        if len < BLOCK {
+            return None;
+        }
+
+        self.encrypt_block(&mut packet[..BLOCK]);
+        if len > BLOCK {
+            self.encrypt_block(&mut packet[len - BLOCK..]);
+        }
+
+        Some(packet)
+    }
+
+    /// Returns `None` for packets shorter than a block.
+    pub fn decrypt(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let mut packet = packet.to_vec();
+        let len = packet.len();
+        if len < BLOCK {
+            return None;
+        }
+
+        if len > BLOCK {
+            self.decrypt_block(&mut packet[len - BLOCK..]);
+        }
+        self.decrypt_block(&mut packet[..BLOCK]);
+
+        Some(packet)
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) {
+        for (byte, iv) in block.iter_mut().zip(self.iv) {
+            *byte ^= iv;
+        }
+        self.cipher
+            .encrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        self.cipher
+            .decrypt_block(GenericArray::from_mut_slice(block));
+        for (byte, iv) in block.iter_mut().zip(self.iv) {
+            *byte ^= iv;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    fn round_trip(key: ([u8; 16], [u8; 16])) {
+        let cipher = GanCipher::new(key, ADDRESS);
+
+        for len in [16, 19, 20, 32] {
+            let packet: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let encrypted = cipher.encrypt(&packet).unwrap();
+
+            assert_eq!(encrypted.len(), len);
+            assert_ne!(encrypted, packet);
+            assert_eq!(cipher.decrypt(&encrypted), Some(packet));
+        }
+    }
+
+    #[test]
+    fn round_trips_gan_packets() {
+        round_trip(GAN_KEY);
+    }
+
+    #[test]
+    fn round_trips_moyu_packets() {
+        round_trip(MOYU_KEY);
+    }
+
+    #[test]
+    fn salts_with_the_address() {
+        let packet = [0; 20];
+        let encrypted = GanCipher::new(GAN_KEY, ADDRESS).encrypt(&packet);
+
+        assert_ne!(GanCipher::new(GAN_KEY, [0; 6]).encrypt(&packet), encrypted);
+        assert_ne!(
+            GanCipher::new(MOYU_KEY, ADDRESS).encrypt(&packet),
+            encrypted
+        );
+    }
+
+    #[test]
+    fn rejects_packets_shorter_than_a_block() {
+        let cipher = GanCipher::new(GAN_KEY, ADDRESS);
+
+        assert_eq!(cipher.encrypt(&[0; 15]), None);
+        assert_eq!(cipher.decrypt(&[0; 15]), None);
+    }
+}
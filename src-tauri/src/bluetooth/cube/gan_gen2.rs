@@ -0,0 +1,274 @@
+//! GAN Gen2 protocol, spoken by GAN cubes from the GAN356 i3 up to the GAN356 i
+//! Carry, and by the MoYu AI 2023.
+
+use uuid::{uuid, Uuid};
+
+use super::{
+    bits::Bits,
+    cube_event::{CubeEvent, CubeMove, Direction, Face, Gyro, HardwareInfo, Quaternion, Vector},
+    facelets::CubieState,
+    CubeCommand,
+};
+
+pub(super) const SERVICE: Uuid = uuid!("6e400001-b5a3-f393-e0a9-e50e24dc4179");
+pub(super) const STATE: Uuid = uuid!("28be4cb6-cd67-11e9-a32f-2a2ae2dbcce4");
+pub(super) const COMMAND: Uuid = uuid!("28be4a4a-cd67-11e9-a32f-2a2ae2dbcce4");
+
+const PACKET_LEN: usize = 20;
+
+const GYRO: u32 = 0x01;
+const MOVE: u32 = 0x02;
+const FACELETS: u32 = 0x04;
+const HARDWARE: u32 = 0x05;
+const BATTERY: u32 = 0x09;
+
+/// The unencrypted command packet.
+pub(super) fn command_packet(command: CubeCommand) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    match command {
+        CubeCommand::RequestFacelets => packet[0] = 0x04,
+        CubeCommand::RequestBattery => packet[0] = 0x09,
+        CubeCommand::RequestHardware => packet[0] = 0x05,
+        CubeCommand::ResetSolved => packet[..14].copy_from_slice(&[
+            0x0A, 0x05, 0x39, 0x77, 0x00, 0x00, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0x00, 0x00,
+        ]),
+    }
+    packet
+}
+
+/// Turns decrypted state packets into cube events. Moves are only reported
+/// once a facelets packet has set the move counter.
+#[derive(Default)]
+pub(super) struct Decoder {
+    last_serial: Option<u8>,
+    /// Local time of the last move, in milliseconds since the Unix epoch.
+    last_move_timestamp: Option<u64>,
+    /// The cube's clock, summed up from the time between moves.
+    cube_timestamp: u64,
+}
+
+impl Decoder {
+    pub fn decode(&mut self, packet: &[u8], timestamp: u64) -> Vec<CubeEvent> {
+        let bits = Bits(packet);
+
+        match bits.get(0, 4) {
+            GYRO => vec![CubeEvent::Gyro(gyro(&bits, 4))],
+            MOVE => self.moves(&bits, timestamp),
+            FACELETS => {
+                let serial = bits.get(4, 8) as u8;
+                self.last_serial.get_or_insert(serial);
+
+                facelets(&bits)
+                    .map(|facelets| CubeEvent::Facelets {
+                        serial: Some(u16::from(serial)),
+                        facelets,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            HARDWARE => vec![CubeEvent::Hardware(HardwareInfo {
+                hardware_name: Some(bits.text(40, 8)),
+                hardware_version: Some(format!("{}.{}", bits.get(8, 8), bits.get(16, 8))),
+                software_version: Some(format!("{}.{}", bits.get(24, 8), bits.get(32, 8))),
+                product_date: None,
+                gyro_supported: Some(bits.get(104, 1) == 1),
+            })],
+            BATTERY => vec![CubeEvent::Battery(bits.get(8, 8).min(100) as u8)],
+            _ => vec![],
+        }
+    }
+
+    /// A move packet repeats up to the last seven moves, newest first.
+    fn moves(&mut self, bits: &Bits, timestamp: u64) -> Vec<CubeEvent> {
+        let Some(last_serial) = self.last_serial else {
+            return vec![];
+        };
+        let serial = bits.get(4, 8) as u8;
+        let missed = serial.wrapping_sub(last_serial).min(7);
+        self.last_serial = Some(serial);
+
+        let mut moves = vec![];
+        for i in (0..missed).rev() {
+            let start = 12 + 5 * usize::from(i);
+            let Some(face) = Face::from_index(bits.get(start, 4)) else {
+                continue;
+            };
+            let direction = if bits.get(start + 4, 1) == 0 {
+                Direction::Clockwise
+            } else {
+                Direction::CounterClockwise
+            };
+
+            // A zero means the cube's 16-bit timer overflowed
+            let elapsed = match u64::from(bits.get(47 + 16 * usize::from(i), 16)) {
+                0 => timestamp.saturating_sub(self.last_move_timestamp.unwrap_or(timestamp)),
+                elapsed => elapsed,
+            };
+            self.cube_timestamp += elapsed;
+
+            moves.push(CubeEvent::Move(CubeMove::new(
+                face,
+                direction,
+                Some(u16::from(serial.wrapping_sub(i))),
+                Some(self.cube_timestamp),
+            )));
+        }
+        if missed > 0 {
+            self.last_move_timestamp = Some(timestamp);
+        }
+
+        moves
+    }
+}
+
+/// Reads a gyro frame starting at bit `start`, which GAN Gen4 cubes share and
+/// MoYu cubes share the orientation of.
+pub(super) fn gyro(bits: &Bits, start: usize) -> Gyro {
+    // Sign and magnitude, scaled to [-1, 1]
+    let component = |start| {
+        let value = bits.get(start, 16);
+        let sign = if value >> 15 == 1 { -1.0 } else { 1.0 };
+        sign * f64::from(value & 0x7FFF) / f64::from(0x7FFF)
+    };
+    let velocity = |start| {
+        let value = bits.get(start, 4);
+        let sign = if value >> 3 == 1 { -1.0 } else { 1.0 };
+        sign * f64::from(value & 0x7)
+    };
+
+    Gyro {
+        quaternion: Quaternion {
+            w: component(start),
+            x: component(start + 16),
+            y: component(start + 32),
+            z: component(start + 48),
+        },
+        velocity: Some(Vector {
+            x: velocity(start + 64),
+            y: velocity(start + 68),
+            z: velocity(start + 72),
+        }),
+    }
+}
+
+/// The last corner and edge are left out of the packet and follow from the rest.
+fn facelets(bits: &Bits) -> Option<String> {
+    let mut state = CubieState::default();
+
+    for i in 0..7 {
+        state.corner_permutation[i] = bits.get(12 + i * 3, 3) as u8;
+        state.corner_orientation[i] = bits.get(33 + i * 2, 2) as u8;
+    }
+    for i in 0..11 {
+        state.edge_permutation[i] = bits.get(47 + i * 4, 4) as u8;
+        state.edge_orientation[i] = bits.get(91 + i, 1) as u8;
+    }
+    state.complete()?;
+
+    state.to_facelets()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gan_cipher::{GanCipher, GAN_KEY};
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    /// Facelets after R, move counter 0x10.
+    const FACELETS_PACKET: [u8; 20] = [
+        0x60, 0x87, 0x2D, 0x0D, 0xC6, 0xBF, 0x12, 0x07, 0x35, 0x03, 0xA3, 0xDC, 0x69, 0x14, 0xB9,
+        0x33, 0x58, 0x02, 0x7E, 0x03,
+    ];
+    /// U then R', 200 and 150 ms after the moves before them, move counter 0x12.
+    const MOVE_PACKET: [u8; 20] = [
+        0x16, 0x64, 0xB1, 0xFB, 0x45, 0xB1, 0x0D, 0x75, 0x84, 0xFA, 0x86, 0x55, 0x76, 0xEA, 0x94,
+        0x5D, 0x92, 0xE4, 0x54, 0x75,
+    ];
+    /// Orientation (w 1, x -1, y 0, z 0), angular velocity (3, -2, 0).
+    const GYRO_PACKET: [u8; 20] = [
+        0x49, 0x0C, 0xBD, 0x9C, 0x6D, 0x99, 0x52, 0x46, 0x52, 0x3A, 0x02, 0x9F, 0xF8, 0x4E, 0xCF,
+        0x4D, 0x36, 0xBF, 0x94, 0x64,
+    ];
+    /// 87 percent.
+    const BATTERY_PACKET: [u8; 20] = [
+        0x47, 0x08, 0x68, 0x05, 0x8F, 0xDA, 0xB5, 0xCB, 0x30, 0xDD, 0x92, 0x38, 0x59, 0x4F, 0x66,
+        0x25, 0x01, 0xB2, 0xCA, 0x17,
+    ];
+
+    fn decode(decoder: &mut Decoder, packet: &[u8]) -> Vec<CubeEvent> {
+        let cipher = GanCipher::new(GAN_KEY, ADDRESS);
+        decoder.decode(&cipher.decrypt(packet).unwrap(), 1_000)
+    }
+
+    #[test]
+    fn decodes_facelets() {
+        let events = decode(&mut Decoder::default(), &FACELETS_PACKET);
+
+        let [CubeEvent::Facelets { serial, facelets }] = events.as_slice() else {
+            panic!("Expected facelets, got {events:?}");
+        };
+        assert_eq!(*serial, Some(0x10));
+        assert_eq!(
+            facelets,
+            "UUFUUFUUFRRRRRRRRRFFDFFDFFDDDBDDBDDBLLLLLLLLLUBBUBBUBB"
+        );
+    }
+
+    #[test]
+    fn decodes_moves_missed_since_the_facelets() {
+        let mut decoder = Decoder::default();
+        assert!(decode(&mut decoder, &MOVE_PACKET).is_empty());
+        decode(&mut decoder, &FACELETS_PACKET);
+
+        let moves: Vec<_> = decode(&mut decoder, &MOVE_PACKET)
+            .into_iter()
+            .map(|event| match event {
+                CubeEvent::Move(cube_move) => cube_move,
+                event => panic!("Expected a move, got {event:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            moves,
+            [
+                CubeMove::new(Face::U, Direction::Clockwise, Some(0x11), Some(200)),
+                CubeMove::new(Face::R, Direction::CounterClockwise, Some(0x12), Some(350)),
+            ]
+        );
+        assert!(decode(&mut decoder, &MOVE_PACKET).is_empty());
+    }
+
+    #[test]
+    fn decodes_gyro() {
+        let events = decode(&mut Decoder::default(), &GYRO_PACKET);
+
+        let [CubeEvent::Gyro(gyro)] = events.as_slice() else {
+            panic!("Expected gyro, got {events:?}");
+        };
+        assert_eq!(
+            gyro.quaternion,
+            Quaternion {
+                w: 1.0,
+                x: -1.0,
+                y: 0.0,
+                z: 0.0
+            }
+        );
+        assert_eq!(
+            gyro.velocity,
+            Some(Vector {
+                x: 3.0,
+                y: -2.0,
+                z: 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_battery() {
+        let events = decode(&mut Decoder::default(), &BATTERY_PACKET);
+
+        assert!(matches!(events.as_slice(), [CubeEvent::Battery(87)]));
+    }
+}
@@ -0,0 +1,216 @@
+//! GAN Gen3 protocol, spoken by the GAN356 i Carry 2 and later cubes.
+
+use uuid::{uuid, Uuid};
+
+use super::{
+    bits::Bits,
+    cube_event::{CubeEvent, CubeMove, Direction, Face, HardwareInfo},
+    facelets::CubieState,
+    CubeCommand,
+};
+
+pub(super) const SERVICE: Uuid = uuid!("8653000a-43e6-47b7-9cb0-5fc21d4ae340");
+pub(super) const STATE: Uuid = uuid!("8653000b-43e6-47b7-9cb0-5fc21d4ae340");
+pub(super) const COMMAND: Uuid = uuid!("8653000c-43e6-47b7-9cb0-5fc21d4ae340");
+
+const PACKET_LEN: usize = 16;
+
+/// First byte of every state packet.
+const MAGIC: u32 = 0x55;
+
+const MOVE: u32 = 0x01;
+const FACELETS: u32 = 0x02;
+const MOVE_HISTORY: u32 = 0x06;
+const HARDWARE: u32 = 0x07;
+const BATTERY: u32 = 0x10;
+
+/// Move packets identify the face by a single set bit, in this order of faces.
+const FACE_BITS: [u32; 6] = [0x02, 0x20, 0x08, 0x01, 0x10, 0x04];
+
+/// Faces as numbered in move history entries, which GAN Gen4 cubes share. Each
+/// face's number is the position of its bit in `FACE_BITS`.
+const HISTORY_FACES: [Face; 6] = [Face::D, Face::U, Face::B, Face::F, Face::L, Face::R];
+
+/// The unencrypted command packet.
+pub(super) fn command_packet(command: CubeCommand) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    match command {
+        CubeCommand::RequestFacelets => packet[..2].copy_from_slice(&[0x68, 0x01]),
+        CubeCommand::RequestBattery => packet[..2].copy_from_slice(&[0x68, 0x07]),
+        CubeCommand::RequestHardware => packet[..2].copy_from_slice(&[0x68, 0x04]),
+        CubeCommand::ResetSolved => packet[..15].copy_from_slice(&[
+            0x68, 0x05, 0x05, 0x39, 0x77, 0x00, 0x00, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0x00,
+            0x00,
+        ]),
+    }
+    packet
+}
+
+/// The unencrypted request for `count` moves up to the one numbered `serial`.
+pub(super) fn history_packet(serial: u8, count: u8) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[..6].copy_from_slice(&[0x68, 0x03, serial, 0x00, count, 0x00]);
+    packet
+}
+
+/// Turns decrypted state packets into cube events. Moves are only reported
+/// once a facelets packet has been received.
+#[derive(Default)]
+pub(super) struct Decoder {
+    synced: bool,
+}
+
+impl Decoder {
+    pub fn decode(&mut self, packet: &[u8]) -> Vec<CubeEvent> {
+        let bits = Bits(packet);
+        if bits.get(0, 8) != MAGIC || bits.get(16, 8) == 0 {
+            return vec![];
+        }
+
+        match bits.get(8, 8) {
+            MOVE if self.synced => cube_move(&bits).map(CubeEvent::Move).into_iter().collect(),
+            MOVE_HISTORY if self.synced => {
+                let length = bits.get(16, 8) as usize;
+                vec![CubeEvent::MoveHistory(move_history(&bits, 24, length))]
+            }
+            FACELETS => {
+                self.synced = true;
+                let serial = bits.get_le(24, 16) as u16;
+
+                facelets(&bits)
+                    .map(|facelets| CubeEvent::Facelets {
+                        serial: Some(serial),
+                        facelets,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            HARDWARE => vec![CubeEvent::Hardware(HardwareInfo {
+                hardware_name: Some(bits.text(40, 5)),
+                hardware_version: Some(format!("{}.{}", bits.get(80, 4), bits.get(84, 4))),
+                software_version: Some(format!("{}.{}", bits.get(72, 4), bits.get(76, 4))),
+                product_date: None,
+                gyro_supported: None,
+            })],
+            BATTERY => vec![CubeEvent::Battery(bits.get(24, 8).min(100) as u8)],
+            _ => vec![],
+        }
+    }
+}
+
+fn cube_move(bits: &Bits) -> Option<CubeMove> {
+    let cube_timestamp = u64::from(bits.get_le(24, 32));
+    let serial = bits.get_le(56, 16) as u16;
+    let direction = if bits.get(72, 2) == 0 {
+        Direction::Clockwise
+    } else {
+        Direction::CounterClockwise
+    };
+    let face_bit = bits.get(74, 6);
+    let face = FACE_BITS.iter().position(|bit| *bit == face_bit)?;
+
+    Some(CubeMove::new(
+        Face::ALL[face],
+        direction,
+        Some(serial),
+        Some(cube_timestamp),
+    ))
+}
+
+/// Reads a move history answer of `length` bytes starting at bit `start`: the
+/// serial of the newest move, then a 3 bit face and a direction bit for each
+/// move, newest first. Entries that aren't faces are padding.
+pub(super) fn move_history(bits: &Bits, start: usize, length: usize) -> Vec<CubeMove> {
+    let newest = bits.get(start, 8) as u8;
+    let fitting = (bits.0.len() * 8).saturating_sub(start + 8) / 4;
+    let count = (length.saturating_sub(1) * 2).min(fitting);
+
+    (0..count)
+        .filter_map(|i| {
+            let entry = start + 8 + i * 4;
+            let face = HISTORY_FACES.get(bits.get(entry, 3) as usize)?;
+            let direction = if bits.get(entry + 3, 1) == 0 {
+                Direction::Clockwise
+            } else {
+                Direction::CounterClockwise
+            };
+
+            Some(CubeMove::new(
+                *face,
+                direction,
+                Some(u16::from(newest.wrapping_sub(i as u8))),
+                None,
+            ))
+        })
+        .collect()
+}
+
+/// The last corner and edge are left out of the packet and follow from the rest.
+fn facelets(bits: &Bits) -> Option<String> {
+    let mut state = CubieState::default();
+
+    for i in 0..7 {
+        state.corner_permutation[i] = bits.get(40 + i * 3, 3) as u8;
+        state.corner_orientation[i] = bits.get(61 + i * 2, 2) as u8;
+    }
+    for i in 0..11 {
+        state.edge_permutation[i] = bits.get(77 + i * 4, 4) as u8;
+        state.edge_orientation[i] = bits.get(121 + i, 1) as u8;
+    }
+    state.complete()?;
+
+    state.to_facelets()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gan_cipher::{GanCipher, GAN_KEY};
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    /// Facelets of the solved cube, move counter 0x1F.
+    const FACELETS_PACKET: [u8; PACKET_LEN] = [
+        0xB8, 0xF6, 0xB1, 0x8C, 0xE3, 0x91, 0xF7, 0x48, 0xA8, 0x60, 0xA2, 0xE6, 0xC3, 0xB2, 0xC0,
+        0x9F,
+    ];
+    /// History after R U R', newest first, move counter 0x21.
+    const HISTORY_PACKET: [u8; PACKET_LEN] = [
+        0x66, 0xA1, 0x79, 0xD2, 0x59, 0xDE, 0xF3, 0x25, 0xB0, 0x3B, 0xFD, 0xCA, 0x81, 0x3D, 0xD0,
+        0xFD,
+    ];
+
+    fn decode(decoder: &mut Decoder, packet: &[u8]) -> Vec<CubeEvent> {
+        let cipher = GanCipher::new(GAN_KEY, ADDRESS);
+        decoder.decode(&cipher.decrypt(packet).unwrap())
+    }
+
+    #[test]
+    fn decodes_move_history() {
+        let mut decoder = Decoder::default();
+        assert!(decode(&mut decoder, &HISTORY_PACKET).is_empty());
+        decode(&mut decoder, &FACELETS_PACKET);
+
+        let events = decode(&mut decoder, &HISTORY_PACKET);
+
+        let [CubeEvent::MoveHistory(moves)] = events.as_slice() else {
+            panic!("Expected a move history, got {events:?}");
+        };
+        assert_eq!(
+            *moves,
+            [
+                CubeMove::new(Face::R, Direction::CounterClockwise, Some(0x21), None),
+                CubeMove::new(Face::U, Direction::Clockwise, Some(0x20), None),
+                CubeMove::new(Face::R, Direction::Clockwise, Some(0x1F), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_history_faces_by_their_move_bits() {
+        for (number, face) in HISTORY_FACES.iter().enumerate() {
+            let position = FACE_BITS.iter().position(|bit| *bit == 1 << number);
+            assert_eq!(position.map(|position| Face::ALL[position]), Some(*face));
+        }
+    }
+}
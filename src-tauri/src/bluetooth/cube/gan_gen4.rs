@@ -0,0 +1,395 @@
+//! GAN Gen4 protocol, spoken by the GAN12 ui Maglev, GAN14 Maglev and later cubes.
+
+use uuid::{uuid, Uuid};
+
+use super::{
+    bits::Bits,
+    cube_event::{CubeEvent, CubeMove, Direction, Face, HardwareInfo},
+    facelets::CubieState,
+    gan_gen2::gyro,
+    gan_gen3::move_history,
+    CubeCommand,
+};
+
+pub(super) const SERVICE: Uuid = uuid!("00000010-0000-fff7-fff6-fff5fff4fff0");
+pub(super) const STATE: Uuid = uuid!("0000fff6-0000-1000-8000-00805f9b34fb");
+pub(super) const COMMAND: Uuid = uuid!("0000fff5-0000-1000-8000-00805f9b34fb");
+
+const PACKET_LEN: usize = 20;
+
+const MOVE: u32 = 0x01;
+const MOVE_HISTORY: u32 = 0xD1;
+const GYRO: u32 = 0xEC;
+const FACELETS: u32 = 0xED;
+const BATTERY: u32 = 0xEF;
+const PRODUCT_DATE: u32 = 0xFA;
+const HARDWARE_NAME: u32 = 0xFC;
+const SOFTWARE_VERSION: u32 = 0xFD;
+const HARDWARE_VERSION: u32 = 0xFE;
+
+/// Move packets identify the face by a single set bit, in this order of faces.
+const FACE_BITS: [u32; 6] = [0x02, 0x20, 0x08, 0x01, 0x10, 0x04];
+
+/// The unencrypted command packet.
+pub(super) fn command_packet(command: CubeCommand) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    match command {
+        CubeCommand::RequestFacelets => {
+            packet[..6].copy_from_slice(&[0xDD, 0x04, 0x00, 0xED, 0x00, 0x00]);
+        }
+        CubeCommand::RequestBattery => {
+            packet[..6].copy_from_slice(&[0xDD, 0x04, 0x00, 0xEF, 0x00, 0x00]);
+        }
+        CubeCommand::RequestHardware => {
+            packet[..5].copy_from_slice(&[0xDF, 0x03, 0x00, 0x00, 0x00]);
+        }
+        CubeCommand::ResetSolved => packet[..16].copy_from_slice(&[
+            0xD2, 0x0D, 0x05, 0x39, 0x77, 0x00, 0x00, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0x00,
+            0x00, 0x00,
+        ]),
+    }
+    packet
+}
+
+/// The unencrypted request for `count` moves up to the one numbered `serial`.
+pub(super) fn history_packet(serial: u8, count: u8) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[..6].copy_from_slice(&[0xD1, 0x04, serial, 0x00, count, 0x00]);
+    packet
+}
+
+/// Turns decrypted state packets into cube events. Moves are only reported
+/// once a facelets packet has been received. Hardware information arrives in
+/// several packets and is reported once all of them are in.
+#[derive(Default)]
+pub(super) struct Decoder {
+    synced: bool,
+    hardware: HardwareInfo,
+}
+
+impl Decoder {
+    pub fn decode(&mut self, packet: &[u8]) -> Vec<CubeEvent> {
+        let bits = Bits(packet);
+        let length = bits.get(8, 8) as usize;
+
+        match bits.get(0, 8) {
+            MOVE if self.synced => cube_move(&bits).map(CubeEvent::Move).into_iter().collect(),
+            MOVE_HISTORY if self.synced => {
+                vec![CubeEvent::MoveHistory(move_history(&bits, 16, length))]
+            }
+            GYRO => vec![CubeEvent::Gyro(gyro(&bits, 16))],
+            FACELETS => {
+                self.synced = true;
+                let serial = bits.get_le(16, 16) as u16;
+
+                facelets(&bits)
+                    .map(|facelets| CubeEvent::Facelets {
+                        serial: Some(serial),
+                        facelets,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            BATTERY => {
+                let level = bits.get(8 + length * 8, 8).min(100) as u8;
+                vec![CubeEvent::Battery(level)]
+            }
+            PRODUCT_DATE => {
+                self.hardware.product_date = Some(format!(
+                    "{}-{:02}-{:02}",
+                    bits.get_le(24, 16),
+                    bits.get(40, 8),
+                    bits.get(48, 8)
+                ));
+                self.hardware_event()
+            }
+            HARDWARE_NAME => {
+                self.hardware.hardware_name = Some(bits.text(24, length.saturating_sub(1)));
+                self.hardware_event()
+            }
+            SOFTWARE_VERSION => {
+                self.hardware.software_version =
+                    Some(format!("{}.{}", bits.get(24, 4), bits.get(28, 4)));
+                self.hardware_event()
+            }
+            HARDWARE_VERSION => {
+                self.hardware.hardware_version =
+                    Some(format!("{}.{}", bits.get(24, 4), bits.get(28, 4)));
+                self.hardware_event()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn hardware_event(&mut self) -> Vec<CubeEvent> {
+        let hardware = &self.hardware;
+        let complete = hardware.product_date.is_some()
+            && hardware.hardware_name.is_some()
+            && hardware.software_version.is_some()
+            && hardware.hardware_version.is_some();
+        if !complete {
+            return vec![];
+        }
+
+        vec![CubeEvent::Hardware(std::mem::take(&mut self.hardware))]
+    }
+}
+
+fn cube_move(bits: &Bits) -> Option<CubeMove> {
+    let cube_timestamp = u64::from(bits.get_le(16, 32));
+    let serial = bits.get_le(48, 16) as u16;
+    let direction = if bits.get(64, 2) == 0 {
+        Direction::Clockwise
+    } else {
+        Direction::CounterClockwise
+    };
+    let face_bit = bits.get(66, 6);
+    let face = FACE_BITS.iter().position(|bit| *bit == face_bit)?;
+
+    Some(CubeMove::new(
+        Face::ALL[face],
+        direction,
+        Some(serial),
+        Some(cube_timestamp),
+    ))
+}
+
+/// The last corner and edge are left out of the packet and follow from the rest.
+fn facelets(bits: &Bits) -> Option<String> {
+    let mut state = CubieState::default();
+
+    for i in 0..7 {
+        state.corner_permutation[i] = bits.get(32 + i * 3, 3) as u8;
+        state.corner_orientation[i] = bits.get(53 + i * 2, 2) as u8;
+    }
+    for i in 0..11 {
+        state.edge_permutation[i] = bits.get(69 + i * 4, 4) as u8;
+        state.edge_orientation[i] = bits.get(113 + i, 1) as u8;
+    }
+    state.complete()?;
+
+    state.to_facelets()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        cube_event::{Quaternion, Vector},
+        gan_cipher::{GanCipher, GAN_KEY},
+    };
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    /// Facelets of the solved cube, move counter 0x0102.
+    const SOLVED_FACELETS_PACKET: [u8; PACKET_LEN] = [
+        0x70, 0x91, 0x5C, 0xE3, 0xD0, 0xA8, 0x79, 0x2C, 0x36, 0xBF, 0x65, 0x5F, 0x65, 0xE7, 0x3B,
+        0x9D, 0x0F, 0xFE, 0x2C, 0xE9,
+    ];
+    /// Facelets after R, move counter 0x0102.
+    const FACELETS_PACKET: [u8; PACKET_LEN] = [
+        0x00, 0x52, 0x03, 0xD1, 0x3B, 0x87, 0xCC, 0x4D, 0x61, 0x6A, 0x9D, 0xCE, 0xC7, 0xE0, 0x92,
+        0xA8, 0x50, 0x18, 0x67, 0x1C,
+    ];
+    /// Facelets with corner 0 in two places.
+    const IMPOSSIBLE_FACELETS_PACKET: [u8; PACKET_LEN] = [
+        0xBB, 0xF3, 0x5E, 0x03, 0x57, 0xD1, 0x86, 0xE5, 0xE2, 0xD9, 0x14, 0x90, 0xE8, 0x69, 0x95,
+        0x44, 0x7C, 0x34, 0xCF, 0xB0,
+    ];
+    /// R at 4660 ms, move 3.
+    const MOVE_PACKET: [u8; PACKET_LEN] = [
+        0x71, 0xD2, 0x92, 0x86, 0xDC, 0x9D, 0x3A, 0x71, 0x13, 0x37, 0xAA, 0xC6, 0x7D, 0x90, 0x8B,
+        0xFF, 0xB7, 0x3F, 0xEF, 0x97,
+    ];
+    /// F' at 65536 ms, move 0x0104.
+    const COUNTER_CLOCKWISE_MOVE_PACKET: [u8; PACKET_LEN] = [
+        0x08, 0x5F, 0xAE, 0x13, 0x20, 0xC4, 0x67, 0xF9, 0x41, 0x7F, 0x55, 0x68, 0x21, 0x02, 0x25,
+        0xE2, 0xF3, 0x63, 0xCC, 0xC9,
+    ];
+    /// Orientation (w 1, x 0, y 0, z -1), angular velocity (1, -1, 0).
+    const GYRO_PACKET: [u8; PACKET_LEN] = [
+        0x13, 0x8C, 0xF2, 0xA7, 0xA7, 0x18, 0x8C, 0x68, 0x17, 0x0F, 0x24, 0xBA, 0x5F, 0x9D, 0x87,
+        0xD0, 0xBF, 0x02, 0xF8, 0x09,
+    ];
+    /// 64 percent.
+    const BATTERY_PACKET: [u8; PACKET_LEN] = [
+        0x69, 0x75, 0x83, 0xFC, 0xB9, 0xD1, 0xDF, 0x77, 0x77, 0x8C, 0x5A, 0x46, 0x60, 0x1E, 0x85,
+        0x85, 0xAF, 0xB8, 0xED, 0xB3,
+    ];
+    /// Above 100 percent, as reported while charging.
+    const FULL_BATTERY_PACKET: [u8; PACKET_LEN] = [
+        0x8C, 0x2F, 0x13, 0x4A, 0x8D, 0x44, 0xCD, 0x31, 0xAB, 0x12, 0x8D, 0x23, 0x9C, 0xF7, 0x07,
+        0xB1, 0x80, 0xE6, 0x5D, 0xE1,
+    ];
+    /// Made 2024-03-09.
+    const PRODUCT_DATE_PACKET: [u8; PACKET_LEN] = [
+        0x81, 0x9E, 0x29, 0xD4, 0x94, 0x3C, 0xFA, 0xDA, 0xD1, 0xC8, 0xBA, 0x3E, 0x9A, 0x24, 0xBD,
+        0x01, 0xE6, 0xCD, 0x06, 0x91,
+    ];
+    /// Named GANui4.
+    const HARDWARE_NAME_PACKET: [u8; PACKET_LEN] = [
+        0x45, 0xC6, 0xF7, 0x6B, 0x9C, 0x3A, 0x78, 0x63, 0xA9, 0x2C, 0xD9, 0x75, 0xAB, 0x92, 0x8F,
+        0x82, 0x35, 0x85, 0x3A, 0x23,
+    ];
+    /// Software 2.1.
+    const SOFTWARE_VERSION_PACKET: [u8; PACKET_LEN] = [
+        0x86, 0x87, 0x4A, 0x2E, 0xF0, 0x8F, 0xAF, 0xA8, 0x3E, 0xE7, 0xC4, 0xDA, 0xC8, 0xEC, 0xCE,
+        0x14, 0x41, 0x08, 0x22, 0x0F,
+    ];
+    /// Hardware 1.3.
+    const HARDWARE_VERSION_PACKET: [u8; PACKET_LEN] = [
+        0x17, 0x20, 0xD3, 0xE5, 0xCD, 0xC9, 0xD7, 0x59, 0x17, 0xD6, 0x2D, 0x48, 0x4D, 0xC7, 0xCC,
+        0xA7, 0x4A, 0xEA, 0xA7, 0x50,
+    ];
+    /// History after R U R', newest first, move counter 5.
+    const HISTORY_PACKET: [u8; PACKET_LEN] = [
+        0xAA, 0x3F, 0xA1, 0x0C, 0x52, 0x61, 0xE0, 0xDC, 0xA6, 0xF2, 0x53, 0x7E, 0xAB, 0xDC, 0x15,
+        0xAB, 0xBC, 0xA9, 0xBF, 0x45,
+    ];
+
+    fn decode(decoder: &mut Decoder, packet: &[u8]) -> Vec<CubeEvent> {
+        let cipher = GanCipher::new(GAN_KEY, ADDRESS);
+        decoder.decode(&cipher.decrypt(packet).unwrap())
+    }
+
+    fn synced_decoder() -> Decoder {
+        let mut decoder = Decoder::default();
+        decode(&mut decoder, &SOLVED_FACELETS_PACKET);
+        decoder
+    }
+
+    #[test]
+    fn decodes_moves_once_synced() {
+        assert!(decode(&mut Decoder::default(), &MOVE_PACKET).is_empty());
+
+        let events = decode(&mut synced_decoder(), &MOVE_PACKET);
+
+        let [CubeEvent::Move(cube_move)] = events.as_slice() else {
+            panic!("Expected a move, got {events:?}");
+        };
+        assert_eq!(
+            *cube_move,
+            CubeMove::new(Face::R, Direction::Clockwise, Some(3), Some(4660))
+        );
+    }
+
+    #[test]
+    fn decodes_counter_clockwise_moves() {
+        let events = decode(&mut synced_decoder(), &COUNTER_CLOCKWISE_MOVE_PACKET);
+
+        let [CubeEvent::Move(cube_move)] = events.as_slice() else {
+            panic!("Expected a move, got {events:?}");
+        };
+        assert_eq!(
+            *cube_move,
+            CubeMove::new(
+                Face::F,
+                Direction::CounterClockwise,
+                Some(0x0104),
+                Some(65536)
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_facelets() {
+        let events = decode(&mut Decoder::default(), &FACELETS_PACKET);
+
+        let [CubeEvent::Facelets { serial, facelets }] = events.as_slice() else {
+            panic!("Expected facelets, got {events:?}");
+        };
+        assert_eq!(*serial, Some(0x0102));
+        assert_eq!(
+            facelets,
+            "UUFUUFUUFRRRRRRRRRFFDFFDFFDDDBDDBDDBLLLLLLLLLUBBUBBUBB"
+        );
+    }
+
+    #[test]
+    fn ignores_impossible_facelets() {
+        assert!(decode(&mut Decoder::default(), &IMPOSSIBLE_FACELETS_PACKET).is_empty());
+    }
+
+    #[test]
+    fn decodes_gyro() {
+        let events = decode(&mut Decoder::default(), &GYRO_PACKET);
+
+        let [CubeEvent::Gyro(gyro)] = events.as_slice() else {
+            panic!("Expected gyro, got {events:?}");
+        };
+        assert_eq!(
+            gyro.quaternion,
+            Quaternion {
+                w: 1.0,
+                x: 0.0,
+                y: 0.0,
+                z: -1.0
+            }
+        );
+        assert_eq!(
+            gyro.velocity,
+            Some(Vector {
+                x: 1.0,
+                y: -1.0,
+                z: 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_battery() {
+        let mut decoder = Decoder::default();
+
+        let events = decode(&mut decoder, &BATTERY_PACKET);
+        assert!(matches!(events.as_slice(), [CubeEvent::Battery(64)]));
+
+        let events = decode(&mut decoder, &FULL_BATTERY_PACKET);
+        assert!(matches!(events.as_slice(), [CubeEvent::Battery(100)]));
+    }
+
+    #[test]
+    fn reports_hardware_once_all_packets_are_in() {
+        let mut decoder = Decoder::default();
+
+        assert!(decode(&mut decoder, &PRODUCT_DATE_PACKET).is_empty());
+        assert!(decode(&mut decoder, &HARDWARE_NAME_PACKET).is_empty());
+        assert!(decode(&mut decoder, &SOFTWARE_VERSION_PACKET).is_empty());
+        let events = decode(&mut decoder, &HARDWARE_VERSION_PACKET);
+
+        let [CubeEvent::Hardware(hardware)] = events.as_slice() else {
+            panic!("Expected hardware, got {events:?}");
+        };
+        assert_eq!(
+            *hardware,
+            HardwareInfo {
+                hardware_name: Some("GANui4".to_string()),
+                hardware_version: Some("1.3".to_string()),
+                software_version: Some("2.1".to_string()),
+                product_date: Some("2024-03-09".to_string()),
+                gyro_supported: None,
+            }
+        );
+
+        // The next report again needs all four
+        assert!(decode(&mut decoder, &HARDWARE_VERSION_PACKET).is_empty());
+    }
+
+    #[test]
+    fn decodes_move_history() {
+        assert!(decode(&mut Decoder::default(), &HISTORY_PACKET).is_empty());
+
+        let events = decode(&mut synced_decoder(), &HISTORY_PACKET);
+
+        let [CubeEvent::MoveHistory(moves)] = events.as_slice() else {
+            panic!("Expected a move history, got {events:?}");
+        };
+        assert_eq!(
+            *moves,
+            [
+                CubeMove::new(Face::R, Direction::CounterClockwise, Some(5), None),
+                CubeMove::new(Face::U, Direction::Clockwise, Some(4), None),
+                CubeMove::new(Face::R, Direction::Clockwise, Some(3), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Protocol of GiiKER cubes and the Xiaomi Mi Smart Magic Cube, which report
+//! their whole state after every move instead of the move itself.
+
+use btleplug::{
+    api::{CharPropFlags, Characteristic, Peripheral as _, WriteType},
+    platform::Peripheral,
+    Error,
+};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{
+    select,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        oneshot,
+    },
+};
+use tracing::{debug, warn};
+use uuid::{uuid, Uuid};
+
+use super::{
+    cube_event::{CubeEvent, CubeMove, Direction, Face},
+    facelets::CubieState,
+    CubeCapabilities, CubeCommand, SmartCube,
+};
+use crate::bluetooth::notifications::Notifications;
+
+const DATA_SERVICE: Uuid = uuid!("0000aadb-0000-1000-8000-00805f9b34fb");
+const STATE: Uuid = uuid!("0000aadc-0000-1000-8000-00805f9b34fb");
+const COMMAND_SERVICE: Uuid = uuid!("0000aaaa-0000-1000-8000-00805f9b34fb");
+const RESPONSE: Uuid = uuid!("0000aaab-0000-1000-8000-00805f9b34fb");
+const COMMAND: Uuid = uuid!("0000aaac-0000-1000-8000-00805f9b34fb");
+
+const BATTERY: u8 = 0xB5;
+const RESET_SOLVED: u8 = 0xA1;
+
+/// Marks state packets obfuscated with `KEY`, at index 18.
+const OBFUSCATED: u8 = 0xA7;
+const KEY: [u8; 36] = [
+    176, 81, 104, 224, 86, 137, 237, 119, 38, 26, 193, 161, 210, 126, 150, 81, 93, 13, 236, 249,
+    89, 235, 88, 24, 113, 81, 214, 131, 130, 199, 2, 169, 39, 165, 171, 41,
+];
+
+/// Facelet indices of the corner positions, in the cube's own numbering.
+const CORNER_FACELETS: [[usize; 3]; 8] = [
+    [26, 15, 29],
+    [20, 8, 9],
+    [18, 38, 6],
+    [24, 27, 44],
+    [51, 35, 17],
+    [45, 11, 2],
+    [47, 0, 36],
+    [53, 42, 33],
+];
+
+/// Facelet indices of the edge positions, in the cube's own numbering.
+const EDGE_FACELETS: [[usize; 2]; 12] = [
+    [25, 28],
+    [23, 12],
+    [19, 7],
+    [21, 41],
+    [32, 16],
+    [5, 10],
+    [3, 37],
+    [30, 43],
+    [52, 34],
+    [48, 14],
+    [46, 1],
+    [50, 39],
+];
+
+/// Corner orientations are counted in opposite directions on alternate positions.
+const TWIST_SIGNS: [i8; 8] = [-1, 1, -1, 1, 1, -1, 1, -1];
+
+/// Faces as numbered in the move history, starting at 1.
+const FACES: [Face; 6] = [Face::B, Face::D, Face::L, Face::U, Face::R, Face::F];
+
+/// Driver for GiiKER and Xiaomi cubes.
+pub(crate) struct GiikerDriver {
+    peripheral: Peripheral,
+    state: Characteristic,
+    command: Characteristic,
+    /// Feeds state packets read on request to the decoding task.
+    read_states: UnboundedSender<Vec<u8>>,
+    /// Dropping this stops the decoding task.
+    _abort: oneshot::Sender<()>,
+}
+
+impl GiikerDriver {
+    /// Starts decoding state packets into events for `on_event`, and reads the
+    /// current state and battery level.
+    pub(super) async fn start<F>(
+        peripheral: Peripheral,
+        notifications: &Notifications,
+        on_event: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(CubeEvent) + Send + 'static,
+    {
+        let services = peripheral.services();
+        let characteristic = |service: Uuid, characteristic: Uuid| {
+            services
+                .iter()
+                .find(|s| s.uuid == service)
+                .and_then(|s| s.characteristics.iter().find(|c| c.uuid == characteristic))
+                .cloned()
+                .ok_or(Error::NoSuchCharacteristic)
+        };
+        let state = characteristic(DATA_SERVICE, STATE)?;
+        let command = characteristic(COMMAND_SERVICE, COMMAND)?;
+
+        let mut states = notifications.subscribe(STATE).await?;
+        let mut responses = notifications.subscribe(RESPONSE).await?;
+        let (read_states, mut read_states_rx) = unbounded_channel::<Vec<u8>>();
+        let (abort_sender, abort_receiver) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+            let mut decoder = Decoder::default();
+
+            loop {
+                let events = select! {
+                    _ = (&mut abort) => break,
+                    packet = states.next() => {
+                        let Some(packet) = packet else { break };
+                        decoder.decode(&packet.value)
+                    }
+                    Some(packet) = read_states_rx.recv() => decoder.decode(&packet),
+                    response = responses.next() => {
+                        let Some(response) = response else { break };
+                        decode_response(&response.value)
+                    }
+                };
+                for event in events {
+                    on_event(event);
+                }
+            }
+        });
+
+        let driver = Self {
+            peripheral,
+            state,
+            command,
+            read_states,
+            _abort: abort_sender,
+        };
+        for command in [CubeCommand::RequestFacelets, CubeCommand::RequestBattery] {
+            if let Err(err) = driver.send(command).await {
+                warn!("Failed to send {command:?} to GiiKER cube: {err:?}");
+            }
+        }
+
+        Ok(driver)
+    }
+}
+
+impl SmartCube for GiikerDriver {
+    fn capabilities(&self) -> CubeCapabilities {
+        CubeCapabilities {
+            state: true,
+            battery: true,
+            hardware: false,
+            reset: true,
+            gyro: false,
+            move_serials: false,
+            move_history: false,
+        }
+    }
+
+    async fn send(&self, command: CubeCommand) -> Result<(), Error> {
+        let opcode = match command {
+            CubeCommand::RequestFacelets => {
+                let value = self.peripheral.read(&self.state).await?;
+                // Only fails once the decoding task has stopped
+                let _ = self.read_states.send(value);
+                return Ok(());
+            }
+            CubeCommand::RequestBattery => BATTERY,
+            CubeCommand::ResetSolved => RESET_SOLVED,
+            CubeCommand::RequestHardware => {
+                return Err(Error::NotSupported(
+                    "GiiKER cubes don't report hardware information".to_string(),
+                ))
+            }
+        };
+        let write_type = if self.command.properties.contains(CharPropFlags::WRITE) {
+            WriteType::WithResponse
+        } else {
+            WriteType::WithoutResponse
+        };
+
+        self.peripheral
+            .write(&self.command, &[opcode], write_type)
+            .await
+    }
+}
+
+/// Turns state packets into cube events. The move that led to a state is only
+/// reported when the previous state is known and differs from it.
+#[derive(Default)]
+struct Decoder {
+    last_facelets: Option<String>,
+}
+
+impl Decoder {
+    fn decode(&mut self, packet: &[u8]) -> Vec<CubeEvent> {
+        let Some(nibbles) = nibbles(packet) else {
+            debug!("Ignoring short GiiKER packet: {packet:?}");
+            return vec![];
+        };
+        let Some(facelets) = facelets(&nibbles) else {
+            debug!("Ignoring inconsistent GiiKER state: {packet:?}");
+            return vec![];
+        };
+
+        let mut events = vec![];
+        if self
+            .last_facelets
+            .as_ref()
+            .is_some_and(|last| *last != facelets)
+        {
+            events.extend(last_move(&nibbles).into_iter().map(CubeEvent::Move));
+        }
+        self.last_facelets = Some(facelets.clone());
+        events.push(CubeEvent::Facelets {
+            serial: None,
+            facelets,
+        });
+
+        events
+    }
+}
+
+fn decode_response(response: &[u8]) -> Vec<CubeEvent> {
+    match response {
+        [BATTERY, level, ..] => vec![CubeEvent::Battery((*level).min(100))],
+        _ => vec![],
+    }
+}
+
+/// Removes the obfuscation, if any, and splits the packet into its 4 bit values.
+fn nibbles(packet: &[u8]) -> Option<Vec<u8>> {
+    let mut packet = packet.get(..20)?.to_vec();
+
+    if packet[18] == OBFUSCATED {
+        let first = usize::from(packet[19] >> 4);
+        let second = usize::from(packet[19] & 0x0F);
+        for (i, byte) in packet.iter_mut().take(18).enumerate() {
+            *byte = byte
+                .wrapping_add(KEY[i + first])
+                .wrapping_add(KEY[i + second]);
+        }
+    }
+
+    Some(
+        packet
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0F])
+            .collect(),
+    )
+}
+
+/// Corner positions 1-8, corner twists, edge positions 1-12 and the edge flips as
+/// a 12 bit field make up the state.
+fn facelets(nibbles: &[u8]) -> Option<String> {
+    let mut state = CubieState::default();
+
+    for i in 0..8 {
+        state.corner_permutation[i] = nibbles[i].checked_sub(1)?;
+        let twist = nibbles[i + 8] as i8 * TWIST_SIGNS[i];
+        state.corner_orientation[i] = (3 + twist).rem_euclid(3) as u8;
+    }
+    for i in 0..12 {
+        state.edge_permutation[i] = nibbles[i + 16].checked_sub(1)?;
+        state.edge_orientation[i] = (nibbles[28 + i / 4] >> (3 - i % 4)) & 1;
+    }
+
+    state.to_facelets_with(&CORNER_FACELETS, &EDGE_FACELETS)
+}
+
+/// The most recent entry of the move history that follows the state. Half turns
+/// are reported as two quarter turns.
+fn last_move(nibbles: &[u8]) -> Vec<CubeMove> {
+    let Some(face) = nibbles[32]
+        .checked_sub(1)
+        .and_then(|face| FACES.get(usize::from(face)))
+    else {
+        return vec![];
+    };
+
+    let quarter_turn = |direction| CubeMove::new(*face, direction, None, None);
+
+    match nibbles[33].wrapping_sub(1) % 7 {
+        0 => vec![quarter_turn(Direction::Clockwise)],
+        1 => vec![quarter_turn(Direction::Clockwise); 2],
+        2 => vec![quarter_turn(Direction::CounterClockwise)],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOLVED: &str = "UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB";
+
+    /// Solved, after U.
+    const PLAIN_PACKET: [u8; 20] = [
+        0x12, 0x34, 0x56, 0x78, 0x33, 0x33, 0x33, 0x33, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0x00,
+        0x00, 0x41, 0x41, 0x41, 0x41,
+    ];
+    /// Solved, after R', obfuscated with key offsets 2 and 5.
+    const OBFUSCATED_PACKET: [u8; 20] = [
+        0x21, 0x67, 0x89, 0xC9, 0x2C, 0xFB, 0x6C, 0x47, 0xD3, 0xFD, 0x33, 0x9D, 0xF7, 0x7F, 0xAA,
+        0x9A, 0x7C, 0xF0, 0xA7, 0x25,
+    ];
+
+    #[test]
+    fn removes_the_obfuscation() {
+        let deobfuscated = nibbles(&OBFUSCATED_PACKET).unwrap();
+
+        assert_eq!(deobfuscated[..32], nibbles(&PLAIN_PACKET).unwrap()[..32]);
+        assert_eq!(deobfuscated[32..36], [0x5, 0x3, 0x4, 0x1]);
+        assert_eq!(nibbles(&PLAIN_PACKET[..19]), None);
+    }
+
+    #[test]
+    fn decodes_facelets() {
+        for packet in [PLAIN_PACKET, OBFUSCATED_PACKET] {
+            assert_eq!(
+                facelets(&nibbles(&packet).unwrap()).as_deref(),
+                Some(SOLVED)
+            );
+        }
+
+        // Corner positions start at 1
+        let mut unnumbered = PLAIN_PACKET;
+        unnumbered[0] = 0x02;
+        assert_eq!(facelets(&nibbles(&unnumbered).unwrap()), None);
+    }
+
+    #[test]
+    fn decodes_the_last_move() {
+        assert_eq!(
+            last_move(&nibbles(&PLAIN_PACKET).unwrap()),
+            [CubeMove::new(Face::U, Direction::Clockwise, None, None)]
+        );
+        assert_eq!(
+            last_move(&nibbles(&OBFUSCATED_PACKET).unwrap()),
+            [CubeMove::new(
+                Face::R,
+                Direction::CounterClockwise,
+                None,
+                None
+            )]
+        );
+
+        let mut half_turn = PLAIN_PACKET;
+        half_turn[16] = 0x62;
+        assert_eq!(
+            last_move(&nibbles(&half_turn).unwrap()),
+            vec![CubeMove::new(Face::F, Direction::Clockwise, None, None); 2]
+        );
+    }
+
+    #[test]
+    fn reports_moves_only_when_the_state_changes() {
+        let mut decoder = Decoder::default();
+
+        assert!(matches!(
+            decoder.decode(&PLAIN_PACKET)[..],
+            [CubeEvent::Facelets { .. }]
+        ));
+        assert!(matches!(
+            decoder.decode(&OBFUSCATED_PACKET)[..],
+            [CubeEvent::Facelets { .. }]
+        ));
+    }
+}
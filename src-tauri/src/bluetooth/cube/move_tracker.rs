@@ -0,0 +1,353 @@
+//! Detection of moves lost on their way from the cube, by the serials cubes
+//! number their moves with, and their recovery from the cube's move history.
+
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use super::cube_event::{CubeEvent, CubeMove};
+
+/// How long the cube gets to answer a move history request.
+pub const HISTORY_TIMEOUT: Duration = Duration::from_millis(1_000);
+
+/// What to do with a move.
+pub enum Tracked {
+    /// Apply and pass on these moves, in order.
+    Moves(Vec<CubeMove>),
+    /// Moves were lost before the one numbered `serial`, which is held back
+    /// with the moves after it until the `missing` ones are recovered.
+    Gap { serial: u16, missing: u8 },
+    /// Held back behind an earlier gap.
+    Held,
+}
+
+/// Follows the serials of a cube's moves. Serials are compared modulo 256, the
+/// range of the smallest move counters.
+#[derive(Default)]
+pub struct MoveTracker {
+    last_serial: Option<u16>,
+    /// Moves that arrived after a gap, oldest first.
+    held: Vec<CubeMove>,
+    /// When the gap was noticed.
+    recovering_since: Option<Instant>,
+}
+
+impl MoveTracker {
+    pub fn track(&mut self, cube_move: CubeMove) -> Tracked {
+        let Some(serial) = cube_move.serial else {
+            return Tracked::Moves(vec![cube_move]);
+        };
+        if self.is_recovering() {
+            self.held.push(cube_move);
+            return Tracked::Held;
+        }
+        let Some(last_serial) = self.last_serial else {
+            self.last_serial = Some(serial);
+            return Tracked::Moves(vec![cube_move]);
+        };
+
+        match distance(last_serial, serial) {
+            1 => {
+                self.last_serial = Some(serial);
+                Tracked::Moves(vec![cube_move])
+            }
+            // Repeated or older than the last move
+            0 | 128.. => {
+                debug!("Ignoring move {serial} after move {last_serial}");
+                Tracked::Moves(vec![])
+            }
+            distance => {
+                self.held.push(cube_move);
+                self.recovering_since = Some(Instant::now());
+                Tracked::Gap {
+                    serial,
+                    missing: distance - 1,
+                }
+            }
+        }
+    }
+
+    /// Fills the gap with moves from the cube's history. Returns the recovered
+    /// and held moves in order, or `None` if the history doesn't cover the gap.
+    pub fn recover(&mut self, history: &[CubeMove]) -> Option<Vec<CubeMove>> {
+        let (Some(last_serial), Some(newest)) = (
+            self.last_serial,
+            self.held.last().and_then(|cube_move| cube_move.serial),
+        ) else {
+            return Some(vec![]);
+        };
+
+        // Held moves keep their cube timestamps, history entries have none and
+        // only the low byte of their serial
+        let is_serial = |serial: u16| {
+            move |cube_move: &&CubeMove| cube_move.serial.is_some_and(|s| s as u8 == serial as u8)
+        };
+        // Counters wrap at 256 or 65536, the newest move tells which
+        let wraps_at_byte = newest < last_serial && last_serial <= 0xFF;
+        let moves = (1..=distance(last_serial, newest))
+            .map(|i| {
+                let serial = if wraps_at_byte {
+                    u16::from((last_serial as u8).wrapping_add(i))
+                } else {
+                    last_serial.wrapping_add(u16::from(i))
+                };
+                if let Some(cube_move) = self.held.iter().find(is_serial(serial)) {
+                    return Some(cube_move.clone());
+                }
+                let mut cube_move = history.iter().find(is_serial(serial))?.clone();
+                cube_move.serial = Some(serial);
+                Some(cube_move)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        self.last_serial = Some(newest);
+        self.held.clear();
+        self.recovering_since = None;
+        Some(moves)
+    }
+
+    /// Continues from the serial of a facelets packet, which already includes
+    /// the held moves up to it. Returns the held moves after it, in order.
+    pub fn resync(&mut self, serial: Option<u16>) -> Vec<CubeMove> {
+        let Some(serial) = serial else {
+            return vec![];
+        };
+        self.recovering_since = None;
+
+        let newer: Vec<_> = std::mem::take(&mut self.held)
+            .into_iter()
+            .filter(|cube_move| {
+                cube_move
+                    .serial
+                    .is_some_and(|s| (1..128).contains(&distance(serial, s)))
+            })
+            .collect();
+        self.last_serial = newer
+            .last()
+            .and_then(|cube_move| cube_move.serial)
+            .or(Some(serial));
+
+        newer
+    }
+
+    /// Gives up on the gap. Returns the desync event, and the held moves, which
+    /// happened but can't be applied to a known state.
+    pub fn desync(&mut self) -> (CubeEvent, Vec<CubeMove>) {
+        let held = std::mem::take(&mut self.held);
+        let expected_serial = self.last_serial.take().unwrap_or_default().wrapping_add(1);
+        let serial = held
+            .first()
+            .and_then(|cube_move| cube_move.serial)
+            .unwrap_or(expected_serial);
+        self.recovering_since = None;
+
+        (
+            CubeEvent::Desync {
+                expected_serial,
+                serial,
+            },
+            held,
+        )
+    }
+
+    pub fn is_recovering(&self) -> bool {
+        self.recovering_since.is_some()
+    }
+
+    /// Whether the cube took too long to answer the history request.
+    pub fn is_overdue(&self) -> bool {
+        self.recovering_since
+            .is_some_and(|since| since.elapsed() >= HISTORY_TIMEOUT)
+    }
+}
+
+fn distance(from: u16, to: u16) -> u8 {
+    (to as u8).wrapping_sub(from as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cube_event::{Direction, Face};
+    use super::*;
+
+    fn cube_move(serial: u16) -> CubeMove {
+        CubeMove::new(
+            Face::R,
+            Direction::Clockwise,
+            Some(serial),
+            Some(u64::from(serial) * 10),
+        )
+    }
+
+    /// History entries only have the low byte of their serial.
+    fn history(serials: &[u16]) -> Vec<CubeMove> {
+        serials
+            .iter()
+            .map(|serial| CubeMove::new(Face::U, Direction::Clockwise, Some(serial & 0xFF), None))
+            .collect()
+    }
+
+    fn serials(moves: &[CubeMove]) -> Vec<u16> {
+        moves
+            .iter()
+            .filter_map(|cube_move| cube_move.serial)
+            .collect()
+    }
+
+    fn tracked_serials(tracked: Tracked) -> Vec<u16> {
+        match tracked {
+            Tracked::Moves(moves) => serials(&moves),
+            Tracked::Gap { serial, missing } => panic!("Gap of {missing} before {serial}"),
+            Tracked::Held => panic!("Held"),
+        }
+    }
+
+    fn tracker_at(serial: u16) -> MoveTracker {
+        let mut tracker = MoveTracker::default();
+        assert!(tracker.resync(Some(serial)).is_empty());
+        tracker
+    }
+
+    #[test]
+    fn passes_consecutive_moves() {
+        let mut tracker = tracker_at(300);
+
+        assert_eq!(tracked_serials(tracker.track(cube_move(301))), [301]);
+        assert_eq!(tracked_serials(tracker.track(cube_move(302))), [302]);
+        assert!(!tracker.is_recovering());
+    }
+
+    #[test]
+    fn ignores_repeated_and_older_moves() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(301));
+
+        assert!(tracked_serials(tracker.track(cube_move(301))).is_empty());
+        assert!(tracked_serials(tracker.track(cube_move(299))).is_empty());
+        assert_eq!(tracked_serials(tracker.track(cube_move(302))), [302]);
+    }
+
+    #[test]
+    fn passes_moves_without_serials() {
+        let mut tracker = tracker_at(300);
+        let unnumbered = CubeMove::new(Face::R, Direction::Clockwise, None, None);
+
+        let Tracked::Moves(moves) = tracker.track(unnumbered.clone()) else {
+            panic!("Expected the move to pass");
+        };
+        assert_eq!(moves, [unnumbered]);
+    }
+
+    #[test]
+    fn holds_moves_after_a_gap() {
+        let mut tracker = tracker_at(300);
+
+        assert!(matches!(
+            tracker.track(cube_move(304)),
+            Tracked::Gap {
+                serial: 304,
+                missing: 3
+            }
+        ));
+        assert!(matches!(tracker.track(cube_move(305)), Tracked::Held));
+        assert!(tracker.is_recovering());
+        assert!(!tracker.is_overdue());
+    }
+
+    #[test]
+    fn recovers_lost_moves_from_the_history() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(304));
+        tracker.track(cube_move(305));
+
+        let moves = tracker.recover(&history(&[304, 303, 302, 301])).unwrap();
+
+        assert_eq!(serials(&moves), [301, 302, 303, 304, 305]);
+        // Held moves keep their cube timestamps, history entries have none
+        let timestamps: Vec<_> = moves.iter().map(|m| m.cube_timestamp).collect();
+        assert_eq!(timestamps, [None, None, None, Some(3040), Some(3050)]);
+        assert!(!tracker.is_recovering());
+        assert_eq!(tracked_serials(tracker.track(cube_move(306))), [306]);
+    }
+
+    #[test]
+    fn fails_to_recover_from_an_incomplete_history() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(304));
+
+        assert!(tracker.recover(&history(&[304, 303])).is_none());
+        assert!(tracker.is_recovering());
+    }
+
+    #[test]
+    fn wraps_serials_around_255() {
+        let mut tracker = tracker_at(254);
+
+        assert_eq!(tracked_serials(tracker.track(cube_move(255))), [255]);
+        assert_eq!(tracked_serials(tracker.track(cube_move(0))), [0]);
+        assert!(matches!(
+            tracker.track(cube_move(2)),
+            Tracked::Gap {
+                serial: 2,
+                missing: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn recovers_moves_across_the_wrap() {
+        let mut tracker = tracker_at(254);
+        tracker.track(cube_move(1));
+
+        let moves = tracker.recover(&history(&[1, 0, 255])).unwrap();
+
+        assert_eq!(serials(&moves), [255, 0, 1]);
+        assert_eq!(tracked_serials(tracker.track(cube_move(2))), [2]);
+    }
+
+    #[test]
+    fn resyncs_from_facelets() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(304));
+        tracker.track(cube_move(305));
+        tracker.track(cube_move(306));
+
+        // The facelets already include the moves up to 305
+        let newer = tracker.resync(Some(305));
+
+        assert_eq!(serials(&newer), [306]);
+        assert!(!tracker.is_recovering());
+        assert_eq!(tracked_serials(tracker.track(cube_move(307))), [307]);
+    }
+
+    #[test]
+    fn ignores_facelets_without_serial() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(304));
+
+        assert!(tracker.resync(None).is_empty());
+        assert!(tracker.is_recovering());
+    }
+
+    #[test]
+    fn desyncs_with_the_held_moves() {
+        let mut tracker = tracker_at(300);
+        tracker.track(cube_move(304));
+        tracker.track(cube_move(305));
+
+        let (desync, held) = tracker.desync();
+
+        assert!(matches!(
+            desync,
+            CubeEvent::Desync {
+                expected_serial: 301,
+                serial: 304
+            }
+        ));
+        assert_eq!(serials(&held), [304, 305]);
+        assert!(!tracker.is_recovering());
+        // Starts over from the next move
+        assert_eq!(tracked_serials(tracker.track(cube_move(310))), [310]);
+        assert_eq!(tracked_serials(tracker.track(cube_move(311))), [311]);
+    }
+}
@@ -0,0 +1,335 @@
+//! Protocol of MoYu WeiLong AI cubes (WCU_MY32), encrypted like GAN's but with
+//! message types of its own.
+
+use std::sync::Arc;
+
+use btleplug::{
+    api::{Characteristic, Peripheral as _},
+    platform::Peripheral,
+    Error,
+};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{select, sync::oneshot};
+use tracing::{debug, warn};
+use uuid::{uuid, Uuid};
+
+use super::{
+    bits::Bits,
+    cube_event::{CubeEvent, CubeMove, Direction, Face, Gyro, HardwareInfo},
+    gan_cipher::{GanCipher, MOYU32_KEY},
+    gan_gen2::gyro,
+    write_encrypted, CubeCapabilities, CubeCommand, SmartCube,
+};
+use crate::bluetooth::notifications::Notifications;
+
+const SERVICE: Uuid = uuid!("0783b03e-7735-b5a0-1760-a305d2795cb0");
+const STATE: Uuid = uuid!("0783b03e-7735-b5a0-1760-a305d2795cb1");
+const COMMAND: Uuid = uuid!("0783b03e-7735-b5a0-1760-a305d2795cb2");
+
+const PACKET_LEN: usize = 20;
+
+const HARDWARE: u32 = 0xA1;
+const FACELETS: u32 = 0xA3;
+const BATTERY: u32 = 0xA4;
+const MOVE: u32 = 0xA5;
+const GYRO: u32 = 0xAB;
+
+/// The cube numbers faces, both in facelets and moves, in this order.
+const FACES: [Face; 6] = [Face::F, Face::B, Face::U, Face::D, Face::L, Face::R];
+
+/// Move packets repeat this many of the latest moves.
+const MOVE_HISTORY: u8 = 5;
+
+/// Driver for MoYu WeiLong AI cubes.
+pub(crate) struct MoYu32Driver {
+    peripheral: Peripheral,
+    command: Characteristic,
+    cipher: Arc<GanCipher>,
+    /// Dropping this stops the decoding task.
+    _abort: oneshot::Sender<()>,
+}
+
+impl MoYu32Driver {
+    /// Starts decoding state packets into events for `on_event`, and asks the
+    /// cube for its hardware, facelets and battery level.
+    pub(super) async fn start<F>(
+        peripheral: Peripheral,
+        notifications: &Notifications,
+        address: [u8; 6],
+        on_event: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(CubeEvent) + Send + 'static,
+    {
+        let command = peripheral
+            .services()
+            .into_iter()
+            .find(|s| s.uuid == SERVICE)
+            .and_then(|s| s.characteristics.into_iter().find(|c| c.uuid == COMMAND))
+            .ok_or(Error::NoSuchCharacteristic)?;
+        let cipher = Arc::new(GanCipher::new(MOYU32_KEY, address));
+
+        let mut packets = notifications.subscribe(STATE).await?;
+        let (abort_sender, abort_receiver) = oneshot::channel();
+        let task_cipher = cipher.clone();
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+            let mut decoder = Decoder::default();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    packet = packets.next() => {
+                        let Some(packet) = packet else { break };
+                        let Some(decrypted) = task_cipher.decrypt(&packet.value) else {
+                            debug!("Ignoring short MoYu packet: {:?}", packet.value);
+                            continue;
+                        };
+                        for event in decoder.decode(&decrypted) {
+                            on_event(event);
+                        }
+                    }
+                }
+            }
+        });
+
+        let driver = Self {
+            peripheral,
+            command,
+            cipher,
+            _abort: abort_sender,
+        };
+        for command in [
+            CubeCommand::RequestHardware,
+            CubeCommand::RequestFacelets,
+            CubeCommand::RequestBattery,
+        ] {
+            if let Err(err) = driver.send(command).await {
+                warn!("Failed to send {command:?} to MoYu cube: {err:?}");
+            }
+        }
+
+        Ok(driver)
+    }
+}
+
+impl SmartCube for MoYu32Driver {
+    fn capabilities(&self) -> CubeCapabilities {
+        CubeCapabilities {
+            state: true,
+            battery: true,
+            hardware: true,
+            reset: false,
+            gyro: true,
+            move_serials: true,
+            move_history: false,
+        }
+    }
+
+    async fn send(&self, command: CubeCommand) -> Result<(), Error> {
+        let opcode = match command {
+            CubeCommand::RequestHardware => HARDWARE,
+            CubeCommand::RequestFacelets => FACELETS,
+            CubeCommand::RequestBattery => BATTERY,
+            CubeCommand::ResetSolved => {
+                return Err(Error::NotSupported(
+                    "MoYu cubes can't be reset to solved".to_string(),
+                ))
+            }
+        };
+        let mut packet = [0; PACKET_LEN];
+        packet[0] = opcode as u8;
+
+        write_encrypted(&self.peripheral, &self.command, &packet, |packet| {
+            self.cipher.encrypt(packet)
+        })
+        .await
+    }
+}
+
+/// Turns decrypted state packets into cube events. Moves are only reported
+/// once a facelets packet has set the move counter.
+#[derive(Default)]
+struct Decoder {
+    last_serial: Option<u8>,
+    /// The cube's clock, summed up from the time between moves.
+    cube_timestamp: u64,
+}
+
+impl Decoder {
+    fn decode(&mut self, packet: &[u8]) -> Vec<CubeEvent> {
+        let bits = Bits(packet);
+
+        match bits.get(0, 8) {
+            HARDWARE => vec![CubeEvent::Hardware(HardwareInfo {
+                hardware_name: Some(bits.text(8, 8)),
+                hardware_version: Some(format!("{}.{}", bits.get(88, 8), bits.get(96, 8))),
+                software_version: Some(format!("{}.{}", bits.get(72, 8), bits.get(80, 8))),
+                product_date: None,
+                gyro_supported: None,
+            })],
+            FACELETS => {
+                let serial = bits.get(152, 8) as u8;
+                self.last_serial = Some(serial);
+
+                facelets(&bits)
+                    .map(|facelets| CubeEvent::Facelets {
+                        serial: Some(u16::from(serial)),
+                        facelets,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            BATTERY => vec![CubeEvent::Battery(bits.get(8, 8).min(100) as u8)],
+            MOVE => self.moves(&bits).into_iter().map(CubeEvent::Move).collect(),
+            // The orientation is laid out like GAN's, without angular velocity
+            GYRO => vec![CubeEvent::Gyro(Gyro {
+                velocity: None,
+                ..gyro(&bits, 8)
+            })],
+            _ => vec![],
+        }
+    }
+
+    /// Move packets carry the milliseconds since the move before each of the
+    /// latest moves, the counter of the newest and the moves themselves, all
+    /// newest first. Those not reported yet are returned oldest first.
+    fn moves(&mut self, bits: &Bits) -> Vec<CubeMove> {
+        let serial = bits.get(88, 8) as u8;
+        let Some(last_serial) = self.last_serial else {
+            return vec![];
+        };
+        self.last_serial = Some(serial);
+        let count = serial.wrapping_sub(last_serial).min(MOVE_HISTORY);
+
+        let mut moves = vec![];
+        for i in (0..usize::from(count)).rev() {
+            self.cube_timestamp += u64::from(bits.get(8 + i * 16, 16));
+
+            let code = bits.get(96 + i * 5, 5);
+            let Some(face) = FACES.get(code as usize >> 1) else {
+                continue;
+            };
+            let direction = if code & 1 == 0 {
+                Direction::Clockwise
+            } else {
+                Direction::CounterClockwise
+            };
+
+            moves.push(CubeMove::new(
+                *face,
+                direction,
+                Some(u16::from(serial.wrapping_sub(i as u8))),
+                Some(self.cube_timestamp),
+            ));
+        }
+
+        moves
+    }
+}
+
+/// Each face is eight 3 bit face numbers around the center, in the cube's order
+/// of faces. Returns `None` for numbers that aren't faces.
+fn facelets(bits: &Bits) -> Option<String> {
+    let mut facelets = String::with_capacity(54);
+
+    for face in Face::ALL {
+        let start = 8 + FACES.iter().position(|f| *f == face)? * 24;
+        for i in 0..8 {
+            if i == 4 {
+                facelets.push_str(&face.to_string());
+            }
+            let sticker = FACES.get(bits.get(start + i * 3, 3) as usize)?;
+            facelets.push_str(&sticker.to_string());
+        }
+    }
+
+    Some(facelets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cube_event::Quaternion;
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    /// Facelets of the solved cube, move counter 0x10.
+    const FACELETS_PACKET: [u8; PACKET_LEN] = [
+        0x60, 0x4F, 0x89, 0x29, 0x58, 0x7D, 0xBF, 0x88, 0x9F, 0x59, 0x3B, 0x6D, 0xD8, 0x8B, 0xF1,
+        0x7B, 0x99, 0xBD, 0xB2, 0x66,
+    ];
+    /// U then R', 200 and 150 ms after the moves before them, move counter 0x12.
+    const MOVE_PACKET: [u8; PACKET_LEN] = [
+        0xAF, 0x59, 0x42, 0x1E, 0x49, 0xDB, 0x26, 0x05, 0xD7, 0x8A, 0x90, 0xF9, 0xFC, 0x7D, 0xB4,
+        0x92, 0x39, 0xA4, 0xC9, 0xEC,
+    ];
+    /// Orientation (w 1, x 0, y 0, z -1).
+    const GYRO_PACKET: [u8; PACKET_LEN] = [
+        0x7F, 0xB4, 0x2C, 0xE7, 0xD2, 0x3B, 0x0F, 0x13, 0xFD, 0xA6, 0x1F, 0x7F, 0x3E, 0xF1, 0xA4,
+        0xAE, 0x49, 0x88, 0x3F, 0x9A,
+    ];
+
+    fn decode(decoder: &mut Decoder, packet: &[u8]) -> Vec<CubeEvent> {
+        let cipher = GanCipher::new(MOYU32_KEY, ADDRESS);
+        decoder.decode(&cipher.decrypt(packet).unwrap())
+    }
+
+    #[test]
+    fn decodes_facelets() {
+        let events = decode(&mut Decoder::default(), &FACELETS_PACKET);
+
+        let [CubeEvent::Facelets { serial, facelets }] = events.as_slice() else {
+            panic!("Expected facelets, got {events:?}");
+        };
+        assert_eq!(*serial, Some(0x10));
+        assert_eq!(
+            facelets,
+            "UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB"
+        );
+    }
+
+    #[test]
+    fn decodes_moves_missed_since_the_facelets() {
+        let mut decoder = Decoder::default();
+        assert!(decode(&mut decoder, &MOVE_PACKET).is_empty());
+        decode(&mut decoder, &FACELETS_PACKET);
+
+        let moves: Vec<_> = decode(&mut decoder, &MOVE_PACKET)
+            .into_iter()
+            .map(|event| match event {
+                CubeEvent::Move(cube_move) => cube_move,
+                event => panic!("Expected a move, got {event:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            moves,
+            [
+                CubeMove::new(Face::U, Direction::Clockwise, Some(0x11), Some(200)),
+                CubeMove::new(Face::R, Direction::CounterClockwise, Some(0x12), Some(350)),
+            ]
+        );
+        assert!(decode(&mut decoder, &MOVE_PACKET).is_empty());
+    }
+
+    #[test]
+    fn decodes_gyro() {
+        let events = decode(&mut Decoder::default(), &GYRO_PACKET);
+
+        let [CubeEvent::Gyro(gyro)] = events.as_slice() else {
+            panic!("Expected gyro, got {events:?}");
+        };
+        assert_eq!(
+            gyro.quaternion,
+            Quaternion {
+                w: 1.0,
+                x: 0.0,
+                y: 0.0,
+                z: -1.0
+            }
+        );
+        assert_eq!(gyro.velocity, None);
+    }
+}
@@ -0,0 +1,387 @@
+//! Protocol of QiYi cubes (QY-QYSC, XMD Tornado V4 i), which expect every
+//! state packet to be acknowledged and stop sending moves otherwise. QiYi
+//! timers share the framing and encryption.
+
+use std::sync::Arc;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use btleplug::{
+    api::{Characteristic, Peripheral as _},
+    platform::Peripheral,
+    Error,
+};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{select, sync::oneshot};
+use tracing::{debug, warn};
+use uuid::{uuid, Uuid};
+
+use super::{
+    cube_event::{CubeEvent, CubeMove, Direction, Face},
+    write_encrypted, CubeCapabilities, CubeCommand, SmartCube,
+};
+use crate::bluetooth::notifications::Notifications;
+
+const SERVICE: Uuid = uuid!("0000fff0-0000-1000-8000-00805f9b34fb");
+/// Carries commands to the cube as well as its state packets.
+const CUBE: Uuid = uuid!("0000fff6-0000-1000-8000-00805f9b34fb");
+
+const KEY: [u8; 16] = [
+    87, 177, 249, 171, 205, 90, 232, 167, 156, 185, 140, 231, 87, 140, 81, 8,
+];
+const BLOCK: usize = 16;
+
+/// First byte of every message, in both directions.
+const MAGIC: u8 = 0xFE;
+
+/// Start of the cube's hello, which its address completes.
+const CUBE_HELLO: [u8; 11] = [
+    0x00, 0x6B, 0x01, 0x00, 0x00, 0x22, 0x06, 0x00, 0x02, 0x08, 0x00,
+];
+
+/// Sent by the cube in answer to the hello, with its whole state.
+const HELLO: u8 = 0x02;
+const STATE_CHANGE: u8 = 0x03;
+
+/// Faces as numbered in the cube's facelets and moves.
+const FACES: [Face; 6] = [Face::L, Face::R, Face::D, Face::U, Face::F, Face::B];
+
+/// State change packets repeat this many moves before the latest one.
+const MOVE_HISTORY: usize = 9;
+
+/// Driver for QiYi cubes.
+pub(crate) struct QiYiDriver {
+    link: Arc<Link>,
+    /// Dropping this stops the decoding task.
+    _abort: oneshot::Sender<()>,
+}
+
+impl QiYiDriver {
+    /// Starts decoding state packets into events for `on_event`, acknowledging
+    /// them as they come, and says hello to make the cube send its state.
+    pub(super) async fn start<F>(
+        peripheral: Peripheral,
+        notifications: &Notifications,
+        address: [u8; 6],
+        on_event: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(CubeEvent) + Send + 'static,
+    {
+        let characteristic = peripheral
+            .services()
+            .into_iter()
+            .find(|s| s.uuid == SERVICE)
+            .and_then(|s| s.characteristics.into_iter().find(|c| c.uuid == CUBE))
+            .ok_or(Error::NoSuchCharacteristic)?;
+        let link = Arc::new(Link::new(peripheral, characteristic, &CUBE_HELLO, address));
+
+        let mut packets = notifications.subscribe(CUBE).await?;
+        let (abort_sender, abort_receiver) = oneshot::channel();
+        let task_link = link.clone();
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+            let mut decoder = Decoder::default();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    packet = packets.next() => {
+                        let Some(packet) = packet else { break };
+                        let Some(message) = task_link.open(&packet.value) else {
+                            debug!("Ignoring invalid QiYi packet: {:?}", packet.value);
+                            continue;
+                        };
+                        let (events, ack) = decoder.decode(&message);
+                        if let Some(ack) = ack {
+                            task_link.acknowledge(&ack).await;
+                        }
+                        for event in events {
+                            on_event(event);
+                        }
+                    }
+                }
+            }
+        });
+
+        let driver = Self {
+            link,
+            _abort: abort_sender,
+        };
+        if let Err(err) = driver.link.hello().await {
+            warn!("Failed to say hello to QiYi cube: {err:?}");
+        }
+
+        Ok(driver)
+    }
+}
+
+impl SmartCube for QiYiDriver {
+    fn capabilities(&self) -> CubeCapabilities {
+        CubeCapabilities {
+            state: true,
+            battery: true,
+            hardware: false,
+            reset: false,
+            gyro: false,
+            move_serials: false,
+            move_history: false,
+        }
+    }
+
+    async fn send(&self, command: CubeCommand) -> Result<(), Error> {
+        match command {
+            // The answer to the hello has both
+            CubeCommand::RequestFacelets | CubeCommand::RequestBattery => self.link.hello().await,
+            CubeCommand::RequestHardware | CubeCommand::ResetSolved => Err(Error::NotSupported(
+                format!("QiYi cubes don't support {command:?}"),
+            )),
+        }
+    }
+}
+
+/// Framing and encryption of the messages exchanged with QiYi devices, which
+/// are greeted with a hello ending with their address.
+pub(crate) struct Link {
+    peripheral: Peripheral,
+    characteristic: Characteristic,
+    cipher: Aes128,
+    hello: Vec<u8>,
+}
+
+impl Link {
+    /// Writes to `characteristic`. The hello is `hello` followed by the
+    /// address, least significant byte first.
+    pub(crate) fn new(
+        peripheral: Peripheral,
+        characteristic: Characteristic,
+        hello: &[u8],
+        address: [u8; 6],
+    ) -> Self {
+        let mut hello = hello.to_vec();
+        hello.extend(address.iter().rev());
+
+        Self {
+            peripheral,
+            characteristic,
+            cipher: Aes128::new(&GenericArray::from(KEY)),
+            hello,
+        }
+    }
+
+    pub(crate) async fn hello(&self) -> Result<(), Error> {
+        self.send(&self.hello).await
+    }
+
+    /// Writes the acknowledgement, and falls back to a hello if that fails, as
+    /// the device stops reporting while a packet is unacknowledged.
+    pub(crate) async fn acknowledge(&self, ack: &[u8]) {
+        let Err(err) = self.send(ack).await else {
+            return;
+        };
+        warn!("Failed to acknowledge QiYi packet, saying hello again: {err:?}");
+
+        if let Err(err) = self.hello().await {
+            warn!("Failed to say hello to QiYi device: {err:?}");
+        }
+    }
+
+    async fn send(&self, content: &[u8]) -> Result<(), Error> {
+        write_encrypted(&self.peripheral, &self.characteristic, content, |content| {
+            Some(seal(&self.cipher, content))
+        })
+        .await
+    }
+
+    /// Decrypts a packet and strips the padding. Returns `None` if the packet
+    /// isn't a whole number of blocks or fails the checksum.
+    pub(crate) fn open(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        open(&self.cipher, packet)
+    }
+}
+
+/// Frames and encrypts a message.
+fn seal(cipher: &Aes128, content: &[u8]) -> Vec<u8> {
+    let mut message = vec![MAGIC, (content.len() + 4) as u8];
+    message.extend(content);
+    message.extend(crc16_modbus(&message).to_le_bytes());
+    message.resize(message.len().div_ceil(BLOCK) * BLOCK, 0);
+
+    for block in message.chunks_exact_mut(BLOCK) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    message
+}
+
+fn open(cipher: &Aes128, packet: &[u8]) -> Option<Vec<u8>> {
+    if packet.is_empty() || !packet.len().is_multiple_of(BLOCK) {
+        return None;
+    }
+
+    let mut message = packet.to_vec();
+    for block in message.chunks_exact_mut(BLOCK) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+    message.truncate(usize::from(*message.get(1)?));
+
+    // The checksum of a message including its own checksum is zero
+    let valid = message.len() >= 3 && message[0] == MAGIC && crc16_modbus(&message) == 0;
+    valid.then_some(message)
+}
+
+/// Turns messages into cube events. Moves are only reported once the answer to
+/// the hello has been received.
+#[derive(Default)]
+struct Decoder {
+    /// Cube clock of the latest move reported, or of the hello.
+    last_timestamp: Option<u32>,
+    battery: Option<u8>,
+}
+
+impl Decoder {
+    /// Returns the events and the acknowledgement to send.
+    fn decode(&mut self, message: &[u8]) -> (Vec<CubeEvent>, Option<Vec<u8>>) {
+        let Some(header) = message.get(2..7) else {
+            return (vec![], None);
+        };
+        let timestamp = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+
+        let mut events = match header[0] {
+            HELLO => {
+                self.last_timestamp = Some(timestamp);
+                facelets(message)
+                    .map(|facelets| CubeEvent::Facelets {
+                        serial: None,
+                        facelets,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            STATE_CHANGE => self.moves(message, timestamp),
+            _ => return (vec![], None),
+        };
+        if let Some(level) = message.get(35).map(|level| (*level).min(100)) {
+            if self.battery.replace(level) != Some(level) {
+                events.push(CubeEvent::Battery(level));
+            }
+        }
+
+        (events, Some(header.to_vec()))
+    }
+
+    /// The latest move is followed by the ones before it, with their times on
+    /// the cube's clock. Those not reported yet are returned oldest first.
+    fn moves(&mut self, message: &[u8], timestamp: u32) -> Vec<CubeEvent> {
+        let Some(last_timestamp) = self.last_timestamp else {
+            return vec![];
+        };
+        self.last_timestamp = Some(timestamp);
+
+        let latest = message.get(34).map(|code| (*code, timestamp));
+        let history = (1..=MOVE_HISTORY).map_while(|i| {
+            let offset = 91 - 5 * i;
+            let entry = message.get(offset..offset + 5)?;
+            let timestamp = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            (timestamp > last_timestamp).then_some((entry[4], timestamp))
+        });
+        let mut moves: Vec<_> = latest.into_iter().chain(history).collect();
+        moves.reverse();
+
+        moves
+            .into_iter()
+            .filter_map(|(code, timestamp)| cube_move(code, timestamp))
+            .map(CubeEvent::Move)
+            .collect()
+    }
+}
+
+/// Moves are numbered from 1, clockwise and counterclockwise for each face. The
+/// cube's clock ticks 1.6 times per millisecond.
+fn cube_move(code: u8, timestamp: u32) -> Option<CubeMove> {
+    let index = code.checked_sub(1)?;
+    let face = FACES.get(usize::from(index >> 1))?;
+    let direction = if code & 1 == 0 {
+        Direction::Clockwise
+    } else {
+        Direction::CounterClockwise
+    };
+
+    Some(CubeMove::new(
+        *face,
+        direction,
+        None,
+        Some(u64::from(timestamp) * 5 / 8),
+    ))
+}
+
+/// The 54 facelets are packed two per byte, low nibble first, face by face in
+/// the cube's order of faces.
+fn facelets(message: &[u8]) -> Option<String> {
+    let packed = message.get(7..34)?;
+    let stickers = packed
+        .iter()
+        .flat_map(|byte| [byte & 0x0F, byte >> 4])
+        .map(|sticker| FACES.get(usize::from(sticker)).map(Face::to_string))
+        .collect::<Option<Vec<_>>>()?;
+
+    let face_stickers = |face: Face| {
+        let index = FACES.iter().position(|f| *f == face).unwrap_or_default();
+        stickers[index * 9..index * 9 + 9].concat()
+    };
+
+    Some(Face::ALL.into_iter().map(face_stickers).collect())
+}
+
+fn crc16_modbus(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> Aes128 {
+        Aes128::new(&GenericArray::from(KEY))
+    }
+
+    #[test]
+    fn computes_modbus_checksums() {
+        assert_eq!(crc16_modbus(b"123456789"), 0x4B37);
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let content = [
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+        ];
+
+        let sealed = seal(&cipher(), &content);
+
+        assert_eq!(sealed.len(), 32);
+        let message = open(&cipher(), &sealed).unwrap();
+        assert_eq!(message[..2], [MAGIC, 17]);
+        assert_eq!(message[2..15], content);
+    }
+
+    #[test]
+    fn rejects_corrupted_packets() {
+        let mut sealed = seal(&cipher(), &[0x01, 0x02, 0x03]);
+        sealed[3] ^= 0x01;
+
+        assert_eq!(open(&cipher(), &sealed), None);
+        assert_eq!(open(&cipher(), &sealed[..15]), None);
+        assert_eq!(open(&cipher(), &[]), None);
+    }
+}
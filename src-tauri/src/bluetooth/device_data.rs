@@ -1,20 +1,30 @@
 use std::collections::HashMap;
 
+use device_info::DeviceInfo;
 use serde::{Deserialize, Serialize};
 use service::ServiceData;
 
-use super::connected_device::ConnectedDevice;
+use super::{connected_device::ConnectedDevice, discovery::device_kind::DeviceKind};
 
 pub mod characteristic;
+pub mod descriptor;
+pub mod device_info;
 pub mod service;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeviceData {
     pub id: String,
+    pub platform_id: String,
     pub name: Option<String>,
     pub address: Option<String>,
+    pub derived_address: Option<String>,
     pub manufacturer_data: Option<HashMap<u16, Vec<u8>>>,
+    pub device_kind: Option<DeviceKind>,
     pub services: Vec<ServiceData>,
+    /// `None` when the device has no Device Information Service.
+    pub device_info: Option<Box<DeviceInfo>>,
+    /// Charge in percent, for devices with the standard Battery Service.
+    pub battery_level: Option<u8>,
 }
 
 impl From<&ConnectedDevice> for DeviceData {
@@ -23,10 +33,15 @@ impl From<&ConnectedDevice> for DeviceData {
 
         Self {
             id: device.device.id.to_string(),
+            platform_id: device.device.platform_id.clone(),
             name: device.device.name.clone(),
             address: device.device.address.clone(),
+            derived_address: device.device.derived_address.clone(),
             manufacturer_data: device.device.manufacturer_data.clone(),
+            device_kind: device.device.device_kind.clone(),
             services,
+            device_info: device.device_info.clone().map(Box::new),
+            battery_level: device.battery_level,
         }
     }
 }
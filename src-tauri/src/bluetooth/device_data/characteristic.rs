@@ -1,25 +1,38 @@
 use btleplug::api::{CharPropFlags, Characteristic};
 use serde::{Deserialize, Serialize};
 
+use super::descriptor::DescriptorData;
+use crate::bluetooth::assigned_numbers::characteristic_name;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CharacteristicData {
     pub uuid: String,
+    pub name: Option<String>,
     pub read: bool,
     pub write: bool,
     pub write_without_response: bool,
     pub notify: bool,
+    pub indicate: bool,
+    pub descriptors: Vec<DescriptorData>,
 }
 
 impl From<&Characteristic> for CharacteristicData {
     fn from(characteristic: &Characteristic) -> Self {
         Self {
             uuid: characteristic.uuid.to_string(),
+            name: characteristic_name(&characteristic.uuid),
             read: characteristic.properties & CharPropFlags::READ != CharPropFlags::empty(),
             write: characteristic.properties & CharPropFlags::WRITE != CharPropFlags::empty(),
             write_without_response: characteristic.properties
                 & CharPropFlags::WRITE_WITHOUT_RESPONSE
                 != CharPropFlags::empty(),
             notify: characteristic.properties & CharPropFlags::NOTIFY != CharPropFlags::empty(),
+            indicate: characteristic.properties & CharPropFlags::INDICATE != CharPropFlags::empty(),
+            descriptors: characteristic
+                .descriptors
+                .iter()
+                .map(|d| d.into())
+                .collect(),
         }
     }
 }
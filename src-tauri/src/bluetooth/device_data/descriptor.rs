@@ -0,0 +1,19 @@
+use btleplug::api::Descriptor;
+use serde::{Deserialize, Serialize};
+
+use crate::bluetooth::assigned_numbers::descriptor_name;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DescriptorData {
+    pub uuid: String,
+    pub name: Option<String>,
+}
+
+impl From<&Descriptor> for DescriptorData {
+    fn from(descriptor: &Descriptor) -> Self {
+        Self {
+            uuid: descriptor.uuid.to_string(),
+            name: descriptor_name(&descriptor.uuid),
+        }
+    }
+}
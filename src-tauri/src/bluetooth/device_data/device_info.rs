@@ -0,0 +1,83 @@
+use btleplug::{
+    api::{bleuuid::uuid_from_u16, Peripheral as _},
+    platform::Peripheral,
+};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Device Information Service
+const DEVICE_INFORMATION_SERVICE: Uuid = uuid_from_u16(0x180A);
+const MANUFACTURER_NAME: Uuid = uuid_from_u16(0x2A29);
+const MODEL_NUMBER: Uuid = uuid_from_u16(0x2A24);
+const SERIAL_NUMBER: Uuid = uuid_from_u16(0x2A25);
+const HARDWARE_REVISION: Uuid = uuid_from_u16(0x2A27);
+const FIRMWARE_REVISION: Uuid = uuid_from_u16(0x2A26);
+const SOFTWARE_REVISION: Uuid = uuid_from_u16(0x2A28);
+
+/// Contents of the standard Device Information Service, read once at connect time.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub manufacturer_name: Option<String>,
+    pub model_number: Option<String>,
+    pub serial_number: Option<String>,
+    pub hardware_revision: Option<String>,
+    pub firmware_revision: Option<String>,
+    pub software_revision: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Reads all Device Information Service characteristics in parallel. Returns
+    /// `None` when the device doesn't expose the service. Characteristics that are
+    /// missing or fail to read are left empty.
+    pub async fn read(peripheral: &Peripheral) -> Option<Self> {
+        let service = peripheral
+            .services()
+            .into_iter()
+            .find(|s| s.uuid == DEVICE_INFORMATION_SERVICE)?;
+
+        let reads = [
+            MANUFACTURER_NAME,
+            MODEL_NUMBER,
+            SERIAL_NUMBER,
+            HARDWARE_REVISION,
+            FIRMWARE_REVISION,
+            SOFTWARE_REVISION,
+        ]
+        .map(|uuid| {
+            let characteristic = service.characteristics.iter().find(|c| c.uuid == uuid);
+
+            async move {
+                let characteristic = characteristic?;
+                match peripheral.read(characteristic).await {
+                    Ok(value) => Some(decode_string(&value)),
+                    Err(err) => {
+                        debug!("Failed to read device information {uuid}: {err:?}");
+                        None
+                    }
+                }
+            }
+        });
+
+        // Results come back in the order the reads were listed above
+        let mut values = join_all(reads).await.into_iter();
+
+        Some(Self {
+            manufacturer_name: values.next().flatten(),
+            model_number: values.next().flatten(),
+            serial_number: values.next().flatten(),
+            hardware_revision: values.next().flatten(),
+            firmware_revision: values.next().flatten(),
+            software_revision: values.next().flatten(),
+        })
+    }
+}
+
+/// DIS strings are UTF-8, but some firmwares pad them with NULs.
+fn decode_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string()
+}
@@ -2,10 +2,12 @@ use btleplug::api::Service;
 use serde::{Deserialize, Serialize};
 
 use super::characteristic::CharacteristicData;
+use crate::bluetooth::assigned_numbers::service_name;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceData {
     pub uuid: String,
+    pub name: Option<String>,
     pub characteristics: Vec<CharacteristicData>,
 }
 
@@ -15,6 +17,7 @@ impl From<&Service> for ServiceData {
 
         Self {
             uuid: service.uuid.to_string(),
+            name: service_name(&service.uuid),
             characteristics,
         }
     }
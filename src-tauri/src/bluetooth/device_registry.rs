@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use btleplug::api::{BDAddr, PeripheralProperties};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::discovery::derived_address::derive_address;
+
+const REGISTRY_DIR: &str = "com.cubeast.connect";
+const REGISTRY_FILE: &str = "devices.json";
+
+/// Devices remembered at most, the least recently seen are forgotten first.
+const MAX_DEVICES: usize = 256;
+
+/// Assigns device IDs that stay the same across machines, adapters and restarts.
+///
+/// btleplug identifies peripherals by a platform-specific ID (a BlueZ object path
+/// on Linux, a CoreBluetooth UUID on macOS). The registry derives a stable ID from
+/// the device's address, or from its advertisement when the platform hides the
+/// address, and remembers which platform IDs it has seen for it. Platform IDs and
+/// addresses derived from the advertisement keep working as aliases of the stable ID.
+#[derive(Clone)]
+pub(crate) struct DeviceRegistry {
+    inner: Arc<Mutex<Registry>>,
+    path: Option<Arc<PathBuf>>,
+    /// Revision of the registry last written, so that a slower save doesn't
+    /// overwrite a newer one.
+    saved_revision: Arc<Mutex<u64>>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct Registry {
+    /// Platform ID or derived address -> stable ID
+    aliases: HashMap<String, String>,
+    /// Stable ID -> when it was last registered, in seconds since the Unix epoch
+    #[serde(default)]
+    last_seen: HashMap<String, u64>,
+    #[serde(skip)]
+    revision: u64,
+}
+
+impl DeviceRegistry {
+    /// Loads the registry persisted in the local data directory, starting with an
+    /// empty one if there is none.
+    pub fn load() -> Self {
+        let path = dirs::data_local_dir().map(|dir| dir.join(REGISTRY_DIR).join(REGISTRY_FILE));
+
+        let mut registry = path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str::<Registry>(&contents)
+                    .inspect_err(|err| warn!("Ignoring malformed device registry: {err}"))
+                    .ok(),
+                Err(err) => {
+                    warn!("Failed to read device registry: {err}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        // Devices saved before last_seen was recorded are the first to go
+        for stable_id in registry.aliases.values() {
+            registry.last_seen.entry(stable_id.clone()).or_default();
+        }
+
+        info!("Loaded {} known device IDs", registry.last_seen.len());
+
+        Self {
+            inner: Arc::new(Mutex::new(registry)),
+            path: path.map(Arc::new),
+            saved_revision: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Returns the stable ID for a peripheral, assigning one on first sight.
+    pub fn register(&self, platform_id: &str, properties: Option<&PeripheralProperties>) -> String {
+        let mut registry = self.inner.lock().expect("Device registry lock poisoned");
+        let revision = registry.revision;
+
+        let stable_id = if let Some(stable_id) = registry.aliases.get(platform_id) {
+            stable_id.clone()
+        } else {
+            // Without an address, nothing tells this device apart from others of
+            // its model. Its platform ID is used as is and not persisted, so that
+            // a stable ID can be assigned once an address is advertised.
+            let Some(stable_id) = properties.and_then(derive_stable_id) else {
+                return platform_id.to_string();
+            };
+
+            registry.insert_alias(platform_id.to_string(), &stable_id);
+            stable_id
+        };
+
+        let derived_address = properties.and_then(|properties| {
+            derive_address(
+                properties.local_name.as_deref(),
+                &properties.manufacturer_data,
+            )
+        });
+        if let Some(derived_address) = derived_address.filter(|address| *address != stable_id) {
+            registry.insert_alias(derived_address, &stable_id);
+        }
+        registry.seen(&stable_id);
+
+        if registry.revision == revision {
+            return stable_id;
+        }
+        let snapshot = registry.clone();
+        drop(registry);
+
+        self.save(&snapshot);
+        stable_id
+    }
+
+    /// Resolves a stable ID or any of its aliases to the stable ID. Unknown IDs are
+    /// returned unchanged.
+    pub fn stable_id(&self, id: &str) -> String {
+        let registry = self.inner.lock().expect("Device registry lock poisoned");
+
+        registry
+            .aliases
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    fn save(&self, registry: &Registry) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut saved_revision = self
+            .saved_revision
+            .lock()
+            .expect("Device registry lock poisoned");
+        if *saved_revision >= registry.revision {
+            return;
+        }
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| {
+                let contents =
+                    serde_json::to_string_pretty(registry).map_err(std::io::Error::from)?;
+                fs::write(path.as_ref(), contents)
+            });
+
+        match result {
+            Ok(()) => *saved_revision = registry.revision,
+            Err(err) => warn!("Failed to save device registry: {err}"),
+        }
+    }
+}
+
+impl Registry {
+    fn insert_alias(&mut self, alias: String, stable_id: &str) {
+        let previous = self.aliases.insert(alias, stable_id.to_string());
+        if previous.as_deref() != Some(stable_id) {
+            self.revision += 1;
+        }
+    }
+
+    /// Records that the device was just registered, forgetting the least recently
+    /// seen devices beyond `MAX_DEVICES`.
+    fn seen(&mut self, stable_id: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        if self.last_seen.insert(stable_id.to_string(), now).is_none() {
+            self.revision += 1;
+        }
+
+        while self.last_seen.len() > MAX_DEVICES {
+            let Some(oldest) = self
+                .last_seen
+                .iter()
+                .min_by_key(|(_, last_seen)| **last_seen)
+                .map(|(stable_id, _)| stable_id.clone())
+            else {
+                break;
+            };
+            self.last_seen.remove(&oldest);
+            self.aliases.retain(|_, stable_id| *stable_id != oldest);
+        }
+    }
+}
+
+fn derive_stable_id(properties: &PeripheralProperties) -> Option<String> {
+    if properties.address != BDAddr::default() {
+        return Some(properties.address.to_string());
+    }
+
+    // macOS hides the address, so fall back to the one many cubes advertise, or
+    // encode in their names
+    derive_address(
+        properties.local_name.as_deref(),
+        &properties.manufacturer_data,
+    )
+}
@@ -1,3 +1,4 @@
+use advertisement_stream::AdvertisementStream;
 use btleplug::{platform::Adapter, Error};
 use discovery_stream::DiscoveryStream;
 use tokio::sync::{
@@ -6,11 +7,17 @@ use tokio::sync::{
 };
 
 use self::{discovery_actor::DiscoveryActor, discovery_message::DiscoveryMessage};
+use super::device_registry::DeviceRegistry;
 
+pub mod advertisement_stream;
+pub mod derived_address;
+pub mod device_filter;
+pub mod device_kind;
 pub mod discovered_device;
 mod discovery_actor;
 mod discovery_message;
 pub mod discovery_stream;
+pub mod signal_strength;
 
 #[derive(Clone)]
 pub(crate) struct Discovery {
@@ -18,8 +25,8 @@ pub(crate) struct Discovery {
 }
 
 impl Discovery {
-    pub fn start(adapter: Adapter) -> Self {
-        let mut actor = DiscoveryActor::new(adapter);
+    pub fn start(adapter: Adapter, registry: DeviceRegistry) -> Self {
+        let mut actor = DiscoveryActor::new(adapter, registry);
         let (tx, rx) = unbounded_channel();
 
         tokio::spawn(async move {
@@ -42,9 +49,27 @@ impl Discovery {
         rx.await.expect("Failed to receive discovery stream")
     }
 
+    pub async fn subscribe_to_advertisements(&self) -> Result<AdvertisementStream, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(DiscoveryMessage::SubscribeToAdvertisements(tx))
+            .expect("Failed to send actor message");
+
+        rx.await.expect("Failed to receive advertisement stream")
+    }
+
     pub async fn unsubscribe(&self) {
         self.tx
             .send(DiscoveryMessage::Unsubscribe)
             .expect("Failed to send actor message");
     }
+
+    pub async fn is_scanning(&self) -> bool {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(DiscoveryMessage::IsScanning(tx))
+            .expect("Failed to send actor message");
+
+        rx.await.expect("Failed to receive scanning state")
+    }
 }
@@ -0,0 +1,119 @@
+use std::{collections::HashMap, pin::Pin};
+
+use btleplug::{
+    api::{Central as _, CentralEvent, Peripheral as _},
+    platform::{Adapter, PeripheralId},
+    Error,
+};
+use futures_util::{Stream, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::bluetooth::{device_registry::DeviceRegistry, timestamp::timestamp};
+
+pub type AdvertisementStream = Pin<Box<dyn Stream<Item = Advertisement> + Send>>;
+
+/// A single advertisement as received by the adapter. Each platform reports
+/// manufacturer data, service data and service UUIDs separately, so only the
+/// parts carried by this particular report are filled in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advertisement {
+    pub device_id: String,
+    pub platform_id: String,
+    /// Represents the time in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub signal_strength: Option<i16>,
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    pub service_data: HashMap<Uuid, Vec<u8>>,
+    pub services: Vec<Uuid>,
+}
+
+/// Criteria an advertisement has to meet to be forwarded. Empty lists match
+/// any advertisement.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdvertisementFilter {
+    /// Stable or platform IDs of the devices of interest.
+    pub device_ids: Vec<String>,
+    /// Company IDs that have to be present in the manufacturer data.
+    pub company_ids: Vec<u16>,
+}
+
+impl AdvertisementFilter {
+    pub fn matches(&self, advertisement: &Advertisement) -> bool {
+        let device = self.device_ids.is_empty()
+            || self
+                .device_ids
+                .iter()
+                .any(|id| *id == advertisement.device_id || *id == advertisement.platform_id);
+        let company = self.company_ids.is_empty()
+            || self
+                .company_ids
+                .iter()
+                .any(|company_id| advertisement.manufacturer_data.contains_key(company_id));
+
+        device && company
+    }
+}
+
+pub(super) async fn advertisement_stream(
+    adapter: Adapter,
+    registry: DeviceRegistry,
+) -> Result<AdvertisementStream, Error> {
+    let events = adapter.events().await?;
+
+    let advertisements = events.filter_map(move |event| {
+        let adapter = adapter.clone();
+        let registry = registry.clone();
+
+        async move {
+            let timestamp = timestamp();
+            let (id, manufacturer_data, service_data, services) = match event {
+                CentralEvent::ManufacturerDataAdvertisement {
+                    id,
+                    manufacturer_data,
+                } => (id, manufacturer_data, HashMap::new(), vec![]),
+                CentralEvent::ServiceDataAdvertisement { id, service_data } => {
+                    (id, HashMap::new(), service_data, vec![])
+                }
+                CentralEvent::ServicesAdvertisement { id, services } => {
+                    (id, HashMap::new(), HashMap::new(), services)
+                }
+                _ => return None,
+            };
+
+            let (platform_id, device_id, signal_strength) =
+                identify(&adapter, &registry, &id).await;
+
+            Some(Advertisement {
+                device_id,
+                platform_id,
+                timestamp,
+                signal_strength,
+                manufacturer_data,
+                service_data,
+                services,
+            })
+        }
+    });
+
+    Ok(Box::pin(advertisements))
+}
+
+/// Looks up the sender's IDs and its most recent RSSI. The platforms don't
+/// attach the RSSI to the advertisement events themselves.
+async fn identify(
+    adapter: &Adapter,
+    registry: &DeviceRegistry,
+    id: &PeripheralId,
+) -> (String, String, Option<i16>) {
+    let platform_id = id.to_string();
+    let properties = match adapter.peripheral(id).await {
+        Ok(peripheral) => peripheral.properties().await.ok().flatten(),
+        Err(_) => None,
+    };
+    let device_id = registry.register(&platform_id, properties.as_ref());
+    let signal_strength = properties.and_then(|properties| properties.rssi);
+
+    (platform_id, device_id, signal_strength)
+}
@@ -0,0 +1,161 @@
+//! Recovers the MAC address of devices that include it in their advertisement,
+//! for platforms such as macOS that don't expose it.
+
+use std::collections::HashMap;
+
+use btleplug::api::BDAddr;
+
+enum Layout {
+    /// The address, least significant byte first, ends the manufacturer data of
+    /// a matching company ID, for devices named with one of the prefixes, or
+    /// any name if there are none.
+    ReversedSuffix {
+        company_id: fn(u16) -> bool,
+        name_prefixes: &'static [&'static str],
+    },
+    /// The last two address bytes are the hex digits ending the name, the rest
+    /// is fixed.
+    NameSuffix { prefix: &'static str, base: [u8; 4] },
+}
+
+/// Checked in order, the first layout yielding an address wins.
+const LAYOUTS: &[Layout] = &[
+    // GAN, company IDs 0x0001, 0x0101, ..., 0xFF01. As other vendors' IDs
+    // match too, the name has to be a GAN one.
+    Layout::ReversedSuffix {
+        company_id: |company_id| company_id & 0xFF == 0x01,
+        name_prefixes: &["GAN", "MG", "AiCube"],
+    },
+    // QiYi
+    Layout::ReversedSuffix {
+        company_id: |company_id| company_id == 0x0504,
+        name_prefixes: &[],
+    },
+    // MoYu WeiLong V10 AI, named e.g. "WCU_MY32_1A2B"
+    Layout::NameSuffix {
+        prefix: "WCU_MY3",
+        base: [0xCF, 0x30, 0x16, 0x00],
+    },
+];
+
+impl Layout {
+    fn address(
+        &self,
+        name: Option<&str>,
+        manufacturer_data: &HashMap<u16, Vec<u8>>,
+    ) -> Option<BDAddr> {
+        match self {
+            Self::ReversedSuffix { .. } => {
+                let data = &manufacturer_data[&self.company_id(name, manufacturer_data)?];
+
+                let mut address = [0; 6];
+                address.copy_from_slice(&data[data.len() - 6..]);
+                address.reverse();
+
+                Some(BDAddr::from(address))
+            }
+            Self::NameSuffix { prefix, base } => {
+                let name = name.filter(|name| name.starts_with(prefix))?;
+                let suffix = name.get(name.len().checked_sub(4)?..)?;
+                let high = u8::from_str_radix(suffix.get(..2)?, 16).ok()?;
+                let low = u8::from_str_radix(suffix.get(2..)?, 16).ok()?;
+
+                Some(BDAddr::from([
+                    base[0], base[1], base[2], base[3], high, low,
+                ]))
+            }
+        }
+    }
+}
+
+impl Layout {
+    /// The company ID whose manufacturer data ends with the address.
+    fn company_id(
+        &self,
+        name: Option<&str>,
+        manufacturer_data: &HashMap<u16, Vec<u8>>,
+    ) -> Option<u16> {
+        let Self::ReversedSuffix {
+            company_id,
+            name_prefixes,
+        } = self
+        else {
+            return None;
+        };
+        if !name_prefixes.is_empty()
+            && !name.is_some_and(|name| name_prefixes.iter().any(|prefix| name.starts_with(prefix)))
+        {
+            return None;
+        }
+
+        manufacturer_data
+            .iter()
+            .find(|(id, data)| company_id(**id) && data.len() >= 6)
+            .map(|(id, _)| *id)
+    }
+}
+
+/// Returns the address from the first known layout the advertisement matches.
+pub fn derive_address(
+    name: Option<&str>,
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+) -> Option<String> {
+    LAYOUTS
+        .iter()
+        .filter_map(|layout| layout.address(name, manufacturer_data))
+        .find(|address| *address != BDAddr::default())
+        .map(|address| address.to_string())
+}
+
+/// Returns the company ID of the manufacturer data the address is derived from,
+/// which is then part of the address rather than a company's, e.g. 0x2A01 for a
+/// GAN cube.
+pub fn address_company_id(
+    name: Option<&str>,
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+) -> Option<u16> {
+    LAYOUTS
+        .iter()
+        .find_map(|layout| layout.company_id(name, manufacturer_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gan_data() -> HashMap<u16, Vec<u8>> {
+        HashMap::from([(0x3401, vec![0x00, 0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12])])
+    }
+
+    #[test]
+    fn derives_gan_addresses() {
+        assert_eq!(
+            derive_address(Some("GAN12ui"), &gan_data()).as_deref(),
+            Some("12:34:56:78:9A:BC")
+        );
+        assert_eq!(
+            address_company_id(Some("GAN12ui"), &gan_data()),
+            Some(0x3401)
+        );
+    }
+
+    #[test]
+    fn ignores_gan_company_ids_of_other_devices() {
+        let data = HashMap::from([(0x0001, vec![0x00; 8])]);
+
+        assert_eq!(derive_address(Some("Nokia"), &data), None);
+        assert_eq!(address_company_id(Some("Nokia"), &data), None);
+    }
+
+    #[test]
+    fn derives_moyu_addresses_from_names() {
+        assert_eq!(
+            derive_address(Some("WCU_MY32_1A2B"), &HashMap::new()).as_deref(),
+            Some("CF:30:16:00:1A:2B")
+        );
+        assert_eq!(
+            address_company_id(Some("WCU_MY32_1A2B"), &HashMap::new()),
+            None
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::discovered_device::DiscoveredDevice;
+
+/// Criteria a discovered device has to meet. Unset criteria match any device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceFilter {
+    /// Stable ID or platform ID of the device.
+    pub id: Option<String>,
+    pub name_prefix: Option<String>,
+    /// Company ID present in the manufacturer data.
+    pub company_id: Option<u16>,
+    /// Service UUID present in the advertisement.
+    pub service: Option<Uuid>,
+    pub min_signal_strength: Option<i16>,
+}
+
+impl DeviceFilter {
+    pub fn matches(&self, device: &DiscoveredDevice) -> bool {
+        let id = self
+            .id
+            .as_ref()
+            .is_none_or(|id| *id == device.id || *id == device.platform_id);
+        let name = self.name_prefix.as_ref().is_none_or(|prefix| {
+            device
+                .name
+                .as_ref()
+                .is_some_and(|name| name.starts_with(prefix))
+        });
+        let company = self.company_id.is_none_or(|company_id| {
+            device
+                .manufacturer_data
+                .as_ref()
+                .is_some_and(|data| data.contains_key(&company_id))
+        });
+        let service = self
+            .service
+            .is_none_or(|service| device.services.contains(&service));
+        let signal_strength = self.min_signal_strength.is_none_or(|min| {
+            device
+                .smoothed_signal_strength
+                .is_some_and(|signal_strength| signal_strength >= min)
+        });
+
+        id && name && company && service && signal_strength
+    }
+}
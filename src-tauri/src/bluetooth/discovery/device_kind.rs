@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// What a discovered device is and which protocol it speaks, as far as can be
+/// told from its advertisement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceKind {
+    pub brand: String,
+    pub family: Option<String>,
+    /// Protocol generation, `None` when the advertisement doesn't tell them apart.
+    pub protocol: Option<String>,
+    pub category: DeviceCategory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceCategory {
+    Cube,
+    Timer,
+}
+
+/// A device matches a rule when it satisfies every criterion the rule sets.
+struct Rule {
+    /// The name has to start with one of these.
+    name_prefixes: &'static [&'static str],
+    /// Some company ID in the manufacturer data has to satisfy this.
+    company_id: Option<fn(u16) -> bool>,
+    /// This service has to be advertised.
+    service: Option<&'static str>,
+    brand: &'static str,
+    family: Option<&'static str>,
+    protocol: Option<&'static str>,
+    category: DeviceCategory,
+}
+
+const GAN_NAMES: &[&str] = &["GAN", "MG", "AiCube"];
+
+/// GAN devices use company IDs 0x0001, 0x0101, ..., 0xFF01.
+fn is_gan_company_id(company_id: u16) -> bool {
+    company_id & 0xFF == 0x01
+}
+
+/// Checked in order, the first matching rule wins, so more specific rules go
+/// first. Support for a new device starts with a rule here.
+const RULES: &[Rule] = &[
+    Rule {
+        name_prefixes: &[],
+        company_id: None,
+        service: Some("00000010-0000-fff7-fff6-fff5fff4fff0"),
+        brand: "GAN",
+        family: Some("GAN Gen4"),
+        protocol: Some("gan-gen4"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &[],
+        company_id: None,
+        service: Some("8653000a-43e6-47b7-9cb0-5fc21d4ae340"),
+        brand: "GAN",
+        family: Some("GAN Gen3"),
+        protocol: Some("gan-gen3"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: GAN_NAMES,
+        company_id: None,
+        service: Some("6e400001-b5a3-f393-e0a9-e50e24dc4179"),
+        brand: "GAN",
+        family: Some("GAN Gen2"),
+        protocol: Some("gan-gen2"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["GAN", "gan", "Gan"],
+        company_id: None,
+        service: Some("0000fff0-0000-1000-8000-00805f9b34fb"),
+        brand: "GAN",
+        family: Some("GAN Smart Timer"),
+        protocol: Some("gan-timer"),
+        category: DeviceCategory::Timer,
+    },
+    // Cubes rarely advertise their services, which leaves the generation to be
+    // found out on connect.
+    Rule {
+        name_prefixes: GAN_NAMES,
+        company_id: Some(is_gan_company_id),
+        service: None,
+        brand: "GAN",
+        family: None,
+        protocol: None,
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["WCU_MY3"],
+        company_id: None,
+        service: None,
+        brand: "MoYu",
+        family: Some("WeiLong V10 AI"),
+        protocol: Some("moyu-my32"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["MHC"],
+        company_id: None,
+        service: None,
+        brand: "MoYu",
+        family: Some("MoYu AI"),
+        protocol: Some("moyu"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["QY-Timer"],
+        company_id: None,
+        service: None,
+        brand: "QiYi",
+        family: Some("QiYi Smart Timer"),
+        protocol: Some("qiyi-timer"),
+        category: DeviceCategory::Timer,
+    },
+    Rule {
+        name_prefixes: &["QY-QYSC", "XMD-TornadoV4-i"],
+        company_id: None,
+        service: None,
+        brand: "QiYi",
+        family: Some("QiYi AI"),
+        protocol: Some("qiyi"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["Mi Smart Magic Cube"],
+        company_id: None,
+        service: None,
+        brand: "Xiaomi",
+        family: Some("Mi Smart Magic Cube"),
+        protocol: Some("giiker"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &[],
+        company_id: None,
+        service: Some("0000aadb-0000-1000-8000-00805f9b34fb"),
+        brand: "GiiKER",
+        family: None,
+        protocol: Some("giiker"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["Gi", "Hi-"],
+        company_id: None,
+        service: None,
+        brand: "GiiKER",
+        family: None,
+        protocol: Some("giiker"),
+        category: DeviceCategory::Cube,
+    },
+    Rule {
+        name_prefixes: &["GoCube", "Rubiks"],
+        company_id: None,
+        service: None,
+        brand: "Particula",
+        family: Some("GoCube"),
+        protocol: Some("gocube"),
+        category: DeviceCategory::Cube,
+    },
+];
+
+impl Rule {
+    fn matches(
+        &self,
+        name: Option<&str>,
+        manufacturer_data: &HashMap<u16, Vec<u8>>,
+        services: &[Uuid],
+    ) -> bool {
+        let name = self.name_prefixes.is_empty()
+            || name.is_some_and(|name| {
+                self.name_prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+            });
+        let company = self
+            .company_id
+            .is_none_or(|matches| manufacturer_data.keys().copied().any(matches));
+        let service = self.service.is_none_or(|service| {
+            services
+                .iter()
+                .any(|uuid| uuid.to_string().eq_ignore_ascii_case(service))
+        });
+
+        name && company && service
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind {
+            brand: self.brand.to_string(),
+            family: self.family.map(str::to_string),
+            protocol: self.protocol.map(str::to_string),
+            category: self.category,
+        }
+    }
+}
+
+/// Classifies a device by the first rule its advertisement matches.
+pub fn classify(
+    name: Option<&str>,
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+    services: &[Uuid],
+) -> Option<DeviceKind> {
+    RULES
+        .iter()
+        .find(|rule| rule.matches(name, manufacturer_data, services))
+        .map(Rule::kind)
+}
@@ -2,30 +2,92 @@ use std::collections::HashMap;
 
 use btleplug::api::PeripheralProperties;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    derived_address::{address_company_id, derive_address},
+    device_kind::{classify, DeviceKind},
+    signal_strength::Proximity,
+};
+use crate::bluetooth::assigned_numbers::company_name;
 
 type ManufacturerData = Option<HashMap<u16, Vec<u8>>>;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DiscoveredDevice {
+    /// Stable ID assigned by the device registry.
     pub id: String,
+    /// ID the platform Bluetooth stack uses for this device, accepted as an alias of `id`.
+    pub platform_id: String,
     pub name: Option<String>,
     pub address: Option<String>,
+    /// Address recovered from the advertisement, for platforms that hide `address`.
+    /// Accepted as an alias of `id`.
+    pub derived_address: Option<String>,
+    /// Last raw RSSI sample.
     pub signal_strength: Option<i16>,
+    /// RSSI averaged over recent samples, steadier than `signal_strength`.
+    pub smoothed_signal_strength: Option<i16>,
+    pub proximity: Option<Proximity>,
     pub manufacturer_data: ManufacturerData,
+    /// Company names for the known keys of `manufacturer_data`.
+    pub manufacturer_names: HashMap<u16, String>,
+    /// Service UUIDs included in the device's advertisements.
+    pub services: Vec<Uuid>,
+    /// Brand and protocol, for recognized cubes and timers.
+    pub device_kind: Option<DeviceKind>,
 }
 
-impl From<(String, PeripheralProperties)> for DiscoveredDevice {
-    fn from(properties: (String, PeripheralProperties)) -> Self {
+impl From<(String, String, PeripheralProperties)> for DiscoveredDevice {
+    fn from(properties: (String, String, PeripheralProperties)) -> Self {
+        let derived_address = derive_address(
+            properties.2.local_name.as_deref(),
+            &properties.2.manufacturer_data,
+        );
+        let device_kind = classify(
+            properties.2.local_name.as_deref(),
+            &properties.2.manufacturer_data,
+            &properties.2.services,
+        );
+
+        let manufacturer_names = manufacturer_names(
+            &properties.2.manufacturer_data,
+            device_kind.as_ref().and(address_company_id(
+                properties.2.local_name.as_deref(),
+                &properties.2.manufacturer_data,
+            )),
+        );
+
         Self {
             id: properties.0,
-            name: properties.1.local_name,
-            signal_strength: properties.1.rssi,
-            address: Some(properties.1.address.to_string()),
-            manufacturer_data: Some(properties.1.manufacturer_data),
+            platform_id: properties.1,
+            name: properties.2.local_name,
+            signal_strength: properties.2.rssi,
+            smoothed_signal_strength: properties.2.rssi,
+            proximity: properties.2.rssi.map(Proximity::from_signal_strength),
+            address: Some(properties.2.address.to_string()),
+            derived_address,
+            manufacturer_names,
+            manufacturer_data: Some(properties.2.manufacturer_data),
+            services: properties.2.services,
+            device_kind,
         }
     }
 }
 
+/// Leaves out `address_company_id`, which carries part of an address rather
+/// than naming a company.
+fn manufacturer_names(
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+    address_company_id: Option<u16>,
+) -> HashMap<u16, String> {
+    manufacturer_data
+        .keys()
+        .filter(|company_id| Some(**company_id) != address_company_id)
+        .filter_map(|company_id| Some((*company_id, company_name(*company_id)?.to_string())))
+        .collect()
+}
+
 impl PartialEq for DiscoveredDevice {
     fn eq(&self, other: &Self) -> bool {
         self.name.eq(&other.name)
@@ -5,23 +5,29 @@ use btleplug::{
 };
 use tokio::sync::mpsc::UnboundedReceiver;
 
-use super::discovery_stream::{self};
+use super::{
+    advertisement_stream::{self, AdvertisementStream},
+    discovery_stream::{self},
+};
 use super::{
     discovered_device::DiscoveredDevice, discovery_message::DiscoveryMessage,
     discovery_stream::DiscoveryStream,
 };
-use tracing::{error, info, trace};
+use crate::bluetooth::device_registry::DeviceRegistry;
+use tracing::{error, info, trace, warn};
 
 pub(super) struct DiscoveryActor {
     adapter: Adapter,
+    registry: DeviceRegistry,
     devices: Vec<DiscoveredDevice>,
     subscribers_count: usize,
 }
 
 impl DiscoveryActor {
-    pub(super) fn new(adapter: Adapter) -> Self {
+    pub(super) fn new(adapter: Adapter, registry: DeviceRegistry) -> Self {
         Self {
             adapter,
+            registry,
             devices: vec![],
             subscribers_count: 0,
         }
@@ -37,33 +43,76 @@ impl DiscoveryActor {
                         error!("Failed to send subscribe response");
                     }
                 }
+                DiscoveryMessage::SubscribeToAdvertisements(tx) => {
+                    let result = self.subscribe_to_advertisements().await;
+
+                    if tx.send(result).is_err() {
+                        error!("Failed to send subscribe to advertisements response");
+                    }
+                }
                 DiscoveryMessage::Unsubscribe => self.unsubscribe().await,
+                DiscoveryMessage::IsScanning(tx) => {
+                    if tx.send(self.subscribers_count > 0).is_err() {
+                        error!("Failed to send scanning state");
+                    }
+                }
             }
         }
     }
 
     async fn subscribe(&mut self) -> Result<DiscoveryStream, Error> {
-        self.subscribers_count += 1;
+        self.add_subscriber().await?;
+
+        let result = discovery_stream::discovery_stream(
+            self.adapter.clone(),
+            self.devices.clone(),
+            self.registry.clone(),
+        )
+        .await;
+        if result.is_err() {
+            self.unsubscribe().await;
+        }
 
-        if self.subscribers_count == 1 {
+        result
+    }
+
+    async fn subscribe_to_advertisements(&mut self) -> Result<AdvertisementStream, Error> {
+        self.add_subscriber().await?;
+
+        let result =
+            advertisement_stream::advertisement_stream(self.adapter.clone(), self.registry.clone())
+                .await;
+        if result.is_err() {
+            self.unsubscribe().await;
+        }
+
+        result
+    }
+
+    /// Counts a new subscriber, starting the scan for the first one. The count is
+    /// left unchanged if the scan fails to start.
+    async fn add_subscriber(&mut self) -> Result<(), Error> {
+        if self.subscribers_count == 0 {
             trace!("First subscriber, starting discovery");
             self.start_discovery().await?;
         }
+        self.subscribers_count += 1;
 
-        let stream =
-            discovery_stream::discovery_stream(self.adapter.clone(), self.devices.clone()).await?;
-
-        Ok(stream)
+        Ok(())
     }
 
     async fn unsubscribe(&mut self) {
-        self.subscribers_count -= 1;
+        let Some(count) = self.subscribers_count.checked_sub(1) else {
+            warn!("Unsubscribe called without a discovery subscriber");
+            return;
+        };
+        self.subscribers_count = count;
 
         if self.subscribers_count == 0 {
             trace!("No more subscribers, stopping discovery");
-            self.stop_discovery()
-                .await
-                .expect("Failed to stop discovery");
+            if let Err(err) = self.stop_discovery().await {
+                error!("Failed to stop discovery: {err:?}");
+            }
         }
     }
 
@@ -1,9 +1,12 @@
 use btleplug::Error;
 use tokio::sync::oneshot::Sender;
 
-use super::discovery_stream::DiscoveryStream;
+use super::{advertisement_stream::AdvertisementStream, discovery_stream::DiscoveryStream};
 
 pub(crate) enum DiscoveryMessage {
     Subscribe(Sender<Result<DiscoveryStream, Error>>),
+    /// Counts as a discovery subscriber, so it is ended with `Unsubscribe`.
+    SubscribeToAdvertisements(Sender<Result<AdvertisementStream, Error>>),
     Unsubscribe,
+    IsScanning(Sender<bool>),
 }
@@ -1,8 +1,8 @@
-use std::pin::Pin;
+use std::{collections::HashMap, pin::Pin};
 
 use btleplug::{
-    api::{Central as _, CentralEvent, Peripheral as _},
-    platform::Adapter,
+    api::{Central as _, CentralEvent, Peripheral as _, PeripheralProperties},
+    platform::{Adapter, PeripheralId},
     Error,
 };
 use futures_util::{
@@ -11,25 +11,37 @@ use futures_util::{
 };
 use tracing::{debug, warn};
 
-use super::discovered_device::DiscoveredDevice;
+use super::{
+    discovered_device::DiscoveredDevice,
+    signal_strength::{DiscoverySort, SignalTracker},
+};
+use crate::bluetooth::device_registry::DeviceRegistry;
 
 pub type DiscoveryStream = Pin<Box<dyn Stream<Item = Vec<DiscoveredDevice>> + Send>>;
 
 pub(super) async fn discovery_stream(
     adapter: Adapter,
     initial: Vec<DiscoveredDevice>,
+    registry: DeviceRegistry,
 ) -> Result<DiscoveryStream, Error> {
     let events = adapter.events().await?;
+    let signals = SignalTracker::default();
 
     let events = events.filter_map(move |event| {
         let adapter = adapter.clone();
+        let registry = registry.clone();
+        let signals = signals.clone();
 
         async move {
+            if let CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) = &event {
+                record_signal_strength(&adapter, id, &signals).await;
+            }
+
             match event.clone() {
                 CentralEvent::DeviceDiscovered(_)
                 | CentralEvent::DeviceUpdated(_)
                 | CentralEvent::ManufacturerDataAdvertisement { .. } => {
-                    match handle_discovery_event(adapter.clone()).await {
+                    match handle_discovery_event(adapter.clone(), &registry, &signals).await {
                         Ok(devices) => Some(devices),
                         Err(err) => {
                             // On Linux/BlueZ, a peripheral's D-Bus object can be
@@ -56,31 +68,58 @@ pub(super) async fn discovery_stream(
     Ok(Box::pin(events))
 }
 
-async fn handle_discovery_event(adapter: Adapter) -> Result<Vec<DiscoveredDevice>, Error> {
+/// Samples the RSSI of the peripheral an event was reported for. Only the
+/// peripheral's own events are sampled so that each advertisement counts once.
+async fn record_signal_strength(adapter: &Adapter, id: &PeripheralId, signals: &SignalTracker) {
+    let Ok(peripheral) = adapter.peripheral(id).await else {
+        return;
+    };
+    if let Ok(Some(PeripheralProperties {
+        rssi: Some(rssi), ..
+    })) = peripheral.properties().await
+    {
+        signals.record(&id.to_string(), rssi);
+    }
+}
+
+async fn handle_discovery_event(
+    adapter: Adapter,
+    registry: &DeviceRegistry,
+    signals: &SignalTracker,
+) -> Result<Vec<DiscoveredDevice>, Error> {
     let peripherals = adapter.peripherals().await?;
 
     let mut discovered_devices = vec![];
 
     for peripheral in peripherals {
         let properties = peripheral.properties().await?;
-        let id = peripheral.id().to_string();
+        let platform_id = peripheral.id().to_string();
+        let id = registry.register(&platform_id, properties.as_ref());
 
-        let device: DiscoveredDevice = if let Some(properties) = properties {
-            (id, properties).into()
+        let mut device: DiscoveredDevice = if let Some(properties) = properties {
+            (id, platform_id, properties).into()
         } else {
             DiscoveredDevice {
                 id,
+                platform_id,
                 name: None,
                 signal_strength: None,
+                smoothed_signal_strength: None,
+                proximity: None,
                 address: None,
+                derived_address: None,
                 manufacturer_data: None,
+                manufacturer_names: HashMap::new(),
+                services: vec![],
+                device_kind: None,
             }
         };
 
+        signals.apply(&mut device);
         discovered_devices.push(device);
     }
 
-    discovered_devices.sort_by(|a, b| a.name.cmp(&b.name));
+    DiscoverySort::Name.sort(&mut discovered_devices);
 
     Ok(discovered_devices)
 }
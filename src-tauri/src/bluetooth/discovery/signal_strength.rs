@@ -0,0 +1,117 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::discovered_device::DiscoveredDevice;
+
+/// Weight of a new sample in the moving average. Lower values smooth more but
+/// follow a device that is actually moving more slowly.
+const SMOOTHING_FACTOR: f64 = 0.3;
+
+/// A device not heard from for this long starts over from its next sample.
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+/// Rough distance from the adapter, estimated from the smoothed signal strength.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Proximity {
+    /// Within about half a metre, e.g. a cube right next to the computer.
+    Immediate,
+    /// Within a few metres.
+    Near,
+    Far,
+}
+
+impl Proximity {
+    pub fn from_signal_strength(signal_strength: i16) -> Self {
+        match signal_strength {
+            -55.. => Self::Immediate,
+            -75..=-56 => Self::Near,
+            _ => Self::Far,
+        }
+    }
+}
+
+/// Order of the device lists sent by discovery.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscoverySort {
+    #[default]
+    Name,
+    /// Closest devices first, by smoothed signal strength.
+    Proximity,
+}
+
+impl DiscoverySort {
+    pub fn sort(self, devices: &mut [DiscoveredDevice]) {
+        match self {
+            Self::Name => devices.sort_by(|a, b| a.name.cmp(&b.name)),
+            Self::Proximity => devices
+                .sort_by(|a, b| compare_signal_strength(b, a).then_with(|| a.name.cmp(&b.name))),
+        }
+    }
+}
+
+/// Devices without a signal strength come last.
+fn compare_signal_strength(a: &DiscoveredDevice, b: &DiscoveredDevice) -> Ordering {
+    match (a.smoothed_signal_strength, b.smoothed_signal_strength) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Exponentially weighted moving average of the RSSI samples of each device,
+/// keyed by platform ID.
+#[derive(Clone, Default)]
+pub(super) struct SignalTracker {
+    inner: Arc<Mutex<HashMap<String, SmoothedSignal>>>,
+}
+
+struct SmoothedSignal {
+    average: f64,
+    last_sample: Instant,
+}
+
+impl SignalTracker {
+    pub fn record(&self, platform_id: &str, signal_strength: i16) {
+        let mut signals = self.inner.lock().expect("Signal tracker lock poisoned");
+        let now = Instant::now();
+        let sample = f64::from(signal_strength);
+
+        signals
+            .entry(platform_id.to_string())
+            .and_modify(|signal| {
+                if now.duration_since(signal.last_sample) > STALE_AFTER {
+                    signal.average = sample;
+                } else {
+                    signal.average += SMOOTHING_FACTOR * (sample - signal.average);
+                }
+                signal.last_sample = now;
+            })
+            .or_insert(SmoothedSignal {
+                average: sample,
+                last_sample: now,
+            });
+    }
+
+    /// Fills in the smoothed signal strength and proximity of `device`, falling
+    /// back to its last raw sample if none were recorded.
+    pub fn apply(&self, device: &mut DiscoveredDevice) {
+        let signals = self.inner.lock().expect("Signal tracker lock poisoned");
+
+        let smoothed = signals
+            .get(&device.platform_id)
+            .map(|signal| signal.average.round() as i16)
+            .or(device.signal_strength);
+
+        device.smoothed_signal_strength = smoothed;
+        device.proximity = smoothed.map(Proximity::from_signal_strength);
+    }
+}
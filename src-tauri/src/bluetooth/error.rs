@@ -28,7 +28,8 @@ pub enum ErrorCode {
     PermissionDenied,
     /// No Bluetooth adapter was detected on this system.
     NoAdapter,
-    /// The requested Bluetooth operation is not supported on this platform.
+    /// The requested Bluetooth operation is not supported on this platform, or
+    /// by the device (category `Device`).
     NotSupported,
     /// A low-level platform runtime error occurred.
     RuntimeError,
@@ -76,6 +77,15 @@ impl AppError {
             code: ErrorCode::InvalidState,
         }
     }
+
+    /// Create an error for a feature the device lacks, e.g. a command a smart
+    /// cube brand has no equivalent for.
+    pub fn not_supported_by_device() -> Self {
+        Self {
+            category: ErrorCategory::Device,
+            code: ErrorCode::NotSupported,
+        }
+    }
 }
 
 impl From<BtleError> for AppError {
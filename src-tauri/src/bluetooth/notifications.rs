@@ -14,7 +14,9 @@ use tokio::sync::{
 use tracing::error;
 use uuid::Uuid;
 
-use crate::bluetooth::notifications::notification_stream::notification_stream;
+use crate::bluetooth::{
+    characteristic_value::ValueSource, notifications::notification_stream::notification_stream,
+};
 
 pub mod notification_stream;
 mod notifications_message;
@@ -99,27 +101,36 @@ impl NotificationsActor {
     }
 
     async fn subscribe(&mut self, characteristic_id: Uuid) -> Result<NotificationStream, Error> {
+        let characteristic = self
+            .peripheral
+            .characteristics()
+            .into_iter()
+            .find(|c| c.uuid == characteristic_id)
+            .ok_or(Error::NoSuchCharacteristic)?;
+
+        // When a characteristic supports both, the platform backends enable
+        // notifications, so indications are only used as a fallback.
+        let source = if characteristic.properties.contains(CharPropFlags::NOTIFY) {
+            ValueSource::Notification
+        } else if characteristic.properties.contains(CharPropFlags::INDICATE) {
+            ValueSource::Indication
+        } else {
+            return Err(Error::NotSupported(
+                "Characteristic does not support notifications or indications".to_string(),
+            ));
+        };
+
         let count = self.subscribers_count.entry(characteristic_id).or_insert(0);
         *count += 1;
 
         if *count == 1 {
-            let characteristic = self
-                .peripheral
-                .characteristics()
-                .into_iter()
-                .find(|c| c.uuid == characteristic_id)
-                .ok_or(Error::NoSuchCharacteristic)?;
-
-            if !characteristic.properties.contains(CharPropFlags::NOTIFY) {
-                return Err(Error::NotSupported(
-                    "Characteristic does not support notifications".to_string(),
-                ));
+            if let Err(err) = self.peripheral.subscribe(&characteristic).await {
+                self.subscribers_count.remove(&characteristic_id);
+                return Err(err);
             }
-
-            self.peripheral.subscribe(&characteristic).await?;
         }
 
-        notification_stream(&self.peripheral, characteristic_id).await
+        notification_stream(&self.peripheral, characteristic_id, source).await
     }
 
     async fn unsubscribe(&mut self, characteristic_id: Uuid) -> Result<(), Error> {
@@ -3,13 +3,17 @@ use std::pin::Pin;
 use btleplug::{api::Peripheral as _, platform::Peripheral, Error};
 use futures_util::{Stream, StreamExt as _};
 
-use crate::bluetooth::{characteristic_value::CharacteristicValue, timestamp::timestamp};
+use crate::bluetooth::{
+    characteristic_value::{CharacteristicValue, ValueSource},
+    timestamp::timestamp,
+};
 
 pub type NotificationStream = Pin<Box<dyn Stream<Item = CharacteristicValue> + Send>>;
 
 pub(super) async fn notification_stream(
     peripheral: &Peripheral,
     characteristic_id: uuid::Uuid,
+    source: ValueSource,
 ) -> Result<NotificationStream, Error> {
     let notifications = peripheral.notifications().await?;
     let notifications = notifications
@@ -17,9 +21,10 @@ pub(super) async fn notification_stream(
             let uuid = notification.uuid;
             async move { uuid == characteristic_id }
         })
-        .map(|notification| CharacteristicValue {
+        .map(move |notification| CharacteristicValue {
             timestamp: timestamp(),
             value: notification.value,
+            source,
         });
 
     Ok(Box::pin(notifications))
@@ -0,0 +1,74 @@
+//! Drivers decoding the proprietary protocols of smart timers into
+//! `TimerEvent`s, next to the cube drivers.
+
+use btleplug::{platform::Peripheral, Error};
+use gan_timer::GanTimerDriver;
+use qiyi_timer::QiYiTimerDriver;
+use timer_event::{TimerEvent, TimerHistory};
+use tracing::{info, warn};
+
+use super::{
+    cube::address, discovery::discovered_device::DiscoveredDevice, notifications::Notifications,
+};
+
+mod gan_timer;
+mod qiyi_timer;
+pub mod timer_event;
+
+pub(crate) enum TimerDriver {
+    Gan(GanTimerDriver),
+    QiYi(QiYiTimerDriver),
+}
+
+impl TimerDriver {
+    /// Reads the times stored on the timer.
+    pub async fn history(&self) -> Result<TimerHistory, Error> {
+        match self {
+            Self::Gan(driver) => driver.history().await,
+            Self::QiYi(driver) => driver.history().await,
+        }
+    }
+}
+
+/// Starts the driver for the protocol `device` was classified with, if there is
+/// one. Returns `None` for other devices.
+pub(super) async fn start_driver<F>(
+    device: &DiscoveredDevice,
+    peripheral: &Peripheral,
+    notifications: &Notifications,
+    on_event: F,
+) -> Option<TimerDriver>
+where
+    F: Fn(TimerEvent) + Send + 'static,
+{
+    let protocol = device.device_kind.as_ref()?.protocol.as_deref()?;
+
+    let result = match protocol {
+        "gan-timer" => GanTimerDriver::start(peripheral.clone(), notifications, on_event)
+            .await
+            .map(TimerDriver::Gan),
+        "qiyi-timer" => QiYiTimerDriver::start(
+            peripheral.clone(),
+            notifications,
+            address(device)?,
+            on_event,
+        )
+        .await
+        .map(TimerDriver::QiYi),
+        _ => return None,
+    };
+
+    match result {
+        Ok(driver) => {
+            info!("Started {protocol} driver for {}", device.id);
+            Some(driver)
+        }
+        Err(err) => {
+            warn!(
+                "Failed to start {protocol} driver for {}: {err:?}",
+                device.id
+            );
+            None
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Protocol of the GAN Smart Timer, which reports state changes on one
+//! characteristic and keeps its last times readable on another.
+
+use btleplug::{
+    api::{Characteristic, Peripheral as _},
+    platform::Peripheral,
+    Error,
+};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{select, sync::oneshot};
+use tracing::debug;
+use uuid::{uuid, Uuid};
+
+use super::timer_event::{TimerEvent, TimerHistory, TimerState};
+use crate::bluetooth::notifications::Notifications;
+
+const SERVICE: Uuid = uuid!("0000fff0-0000-1000-8000-00805f9b34fb");
+const STATE: Uuid = uuid!("0000fff5-0000-1000-8000-00805f9b34fb");
+const TIMES: Uuid = uuid!("0000fff2-0000-1000-8000-00805f9b34fb");
+
+/// First byte of every state packet.
+const MAGIC: u8 = 0xFE;
+
+/// Driver for GAN Smart Timers.
+pub(crate) struct GanTimerDriver {
+    peripheral: Peripheral,
+    times: Characteristic,
+    /// Dropping this stops the decoding task.
+    _abort: oneshot::Sender<()>,
+}
+
+impl GanTimerDriver {
+    /// Starts decoding state packets into events for `on_event`.
+    pub(super) async fn start<F>(
+        peripheral: Peripheral,
+        notifications: &Notifications,
+        on_event: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(TimerEvent) + Send + 'static,
+    {
+        let times = peripheral
+            .services()
+            .into_iter()
+            .find(|s| s.uuid == SERVICE)
+            .and_then(|s| s.characteristics.into_iter().find(|c| c.uuid == TIMES))
+            .ok_or(Error::NoSuchCharacteristic)?;
+
+        let mut packets = notifications.subscribe(STATE).await?;
+        let (abort_sender, abort_receiver) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    packet = packets.next() => {
+                        let Some(packet) = packet else { break };
+                        for event in decode(&packet.value) {
+                            on_event(event);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            peripheral,
+            times,
+            _abort: abort_sender,
+        })
+    }
+
+    /// Reads the time on the display and the three before it.
+    pub(super) async fn history(&self) -> Result<TimerHistory, Error> {
+        let value = self.peripheral.read(&self.times).await?;
+        let mut times = value.chunks_exact(4).map(time);
+
+        Ok(TimerHistory {
+            display_time_ms: times.next().ok_or(Error::UnexpectedCharacteristic)?,
+            previous_times_ms: times.collect(),
+        })
+    }
+}
+
+fn decode(packet: &[u8]) -> Vec<TimerEvent> {
+    if !is_valid(packet) {
+        debug!("Ignoring invalid GAN timer packet: {packet:?}");
+        return vec![];
+    }
+
+    let state = match packet[3] {
+        0 => TimerState::Disconnected,
+        1 => TimerState::GetSet,
+        2 => TimerState::HandsOff,
+        3 => TimerState::Running,
+        4 => TimerState::Stopped,
+        5 => TimerState::Idle,
+        6 => TimerState::HandsOn,
+        7 => TimerState::Finished,
+        _ => return vec![],
+    };
+
+    let mut events = vec![TimerEvent::State(state)];
+    if state == TimerState::Stopped {
+        if let Some(recorded) = packet.get(4..8) {
+            events.push(TimerEvent::Time(time(recorded)));
+        }
+    }
+    events
+}
+
+/// Packets start with the magic byte and end with a little endian CRC of
+/// everything after the first two bytes.
+fn is_valid(packet: &[u8]) -> bool {
+    if packet.len() < 6 || packet[0] != MAGIC {
+        return false;
+    }
+
+    let (body, crc) = packet.split_at(packet.len() - 2);
+    u16::from_le_bytes([crc[0], crc[1]]) == crc16_ccitt(&body[2..])
+}
+
+fn crc16_ccitt(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Minutes, seconds and little endian milliseconds, in milliseconds.
+fn time(bytes: &[u8]) -> u32 {
+    let minutes = u32::from(bytes[0]);
+    let seconds = u32::from(bytes[1]);
+    let milliseconds = u32::from(u16::from_le_bytes([bytes[2], bytes[3]]));
+
+    minutes * 60_000 + seconds * 1_000 + milliseconds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stopped at 1:11.346.
+    const STOPPED_PACKET: [u8; 10] = [0xFE, 0x08, 0x01, 0x04, 0x01, 0x0B, 0x5A, 0x01, 0xA6, 0xB5];
+    /// Running.
+    const RUNNING_PACKET: [u8; 10] = [0xFE, 0x08, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x62, 0xA5];
+
+    #[test]
+    fn computes_ccitt_crcs() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn decodes_states_and_stopped_times() {
+        assert!(matches!(
+            decode(&RUNNING_PACKET)[..],
+            [TimerEvent::State(TimerState::Running)]
+        ));
+        assert!(matches!(
+            decode(&STOPPED_PACKET)[..],
+            [
+                TimerEvent::State(TimerState::Stopped),
+                TimerEvent::Time(71_346)
+            ]
+        ));
+    }
+
+    #[test]
+    fn ignores_corrupted_packets() {
+        let mut packet = STOPPED_PACKET;
+        packet[5] = 0x0C;
+
+        assert!(!is_valid(&packet));
+        assert!(decode(&packet).is_empty());
+    }
+}
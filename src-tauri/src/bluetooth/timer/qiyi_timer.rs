@@ -0,0 +1,187 @@
+//! Protocol of the QiYi Smart Timer, framed and encrypted like the messages of
+//! QiYi cubes. The timer repeats every message until it is acknowledged.
+
+use std::sync::Arc;
+
+use btleplug::{api::Peripheral as _, platform::Peripheral, Error};
+use futures_util::{FutureExt as _, StreamExt as _};
+use tokio::{select, sync::oneshot};
+use tracing::{debug, warn};
+use uuid::{uuid, Uuid};
+
+use super::timer_event::{TimerEvent, TimerHistory, TimerState};
+use crate::bluetooth::{cube::qiyi::Link, notifications::Notifications};
+
+const SERVICE: Uuid = uuid!("0000fd50-0000-1000-8000-00805f9b34fb");
+const WRITE: Uuid = uuid!("00000001-0000-1001-8001-00805f9b07d0");
+const NOTIFY: Uuid = uuid!("00000002-0000-1001-8001-00805f9b07d0");
+
+/// Start of the timer's hello, which its address completes.
+const TIMER_HELLO: [u8; 11] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x08, 0x00, 0x01, 0x05, 0x5A,
+];
+
+const STATE_CHANGE: u16 = 0x1003;
+
+/// Driver for QiYi Smart Timers.
+pub(crate) struct QiYiTimerDriver {
+    /// Dropping this stops the decoding task.
+    _abort: oneshot::Sender<()>,
+}
+
+impl QiYiTimerDriver {
+    /// Starts decoding messages into events for `on_event`, acknowledging them
+    /// as they come, and says hello to make the timer report.
+    pub(super) async fn start<F>(
+        peripheral: Peripheral,
+        notifications: &Notifications,
+        address: [u8; 6],
+        on_event: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(TimerEvent) + Send + 'static,
+    {
+        let characteristic = peripheral
+            .services()
+            .into_iter()
+            .find(|s| s.uuid == SERVICE)
+            .and_then(|s| s.characteristics.into_iter().find(|c| c.uuid == WRITE))
+            .ok_or(Error::NoSuchCharacteristic)?;
+        let link = Arc::new(Link::new(peripheral, characteristic, &TIMER_HELLO, address));
+
+        let mut packets = notifications.subscribe(NOTIFY).await?;
+        let (abort_sender, abort_receiver) = oneshot::channel();
+        let task_link = link.clone();
+
+        tokio::spawn(async move {
+            let mut abort = Box::pin(abort_receiver).fuse();
+
+            loop {
+                select! {
+                    _ = (&mut abort) => break,
+                    packet = packets.next() => {
+                        let Some(packet) = packet else { break };
+                        let Some(message) = task_link.open(&packet.value) else {
+                            debug!("Ignoring invalid QiYi timer packet: {:?}", packet.value);
+                            continue;
+                        };
+                        let (events, ack) = decode(&message);
+                        if let Some(ack) = ack {
+                            task_link.acknowledge(&ack).await;
+                        }
+                        for event in events {
+                            on_event(event);
+                        }
+                    }
+                }
+            }
+        });
+
+        if let Err(err) = link.hello().await {
+            warn!("Failed to say hello to QiYi timer: {err:?}");
+        }
+
+        Ok(Self {
+            _abort: abort_sender,
+        })
+    }
+
+    /// QiYi timers don't expose their stored times.
+    pub(super) async fn history(&self) -> Result<TimerHistory, Error> {
+        Err(Error::NotSupported(
+            "QiYi timers don't expose their stored times".to_string(),
+        ))
+    }
+}
+
+/// Messages are laid out as `FE length | sequence number (4) | 00 | command (2)
+/// | data length | data | CRC`. Every message is acknowledged by echoing its
+/// sequence number and command.
+///
+/// Not yet checked against a captured session: the state codes, the big endian
+/// time in milliseconds following the state, and the acknowledgement being the
+/// seven bytes after the length.
+fn decode(message: &[u8]) -> (Vec<TimerEvent>, Option<Vec<u8>>) {
+    let Some(header) = message.get(2..9) else {
+        return (vec![], None);
+    };
+    let ack = Some(header.to_vec());
+
+    if u16::from_be_bytes([header[5], header[6]]) != STATE_CHANGE {
+        return (vec![], ack);
+    }
+    let state = match message.get(10) {
+        Some(0) => TimerState::Idle,
+        Some(1) => TimerState::HandsOn,
+        Some(2) => TimerState::GetSet,
+        Some(3) => TimerState::Running,
+        Some(4) => TimerState::Stopped,
+        _ => return (vec![], ack),
+    };
+
+    let mut events = vec![TimerEvent::State(state)];
+    if state == TimerState::Stopped {
+        if let Some(time) = message.get(11..15) {
+            events.push(TimerEvent::Time(u32::from_be_bytes([
+                time[0], time[1], time[2], time[3],
+            ])));
+        }
+    }
+    (events, ack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A state change message with sequence number 7, before the checksum.
+    fn state_change(data: &[u8]) -> Vec<u8> {
+        let mut message = vec![0xFE, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x10, 0x03];
+        message.push(data.len() as u8);
+        message.extend(data);
+        message[1] = (message.len() + 2) as u8;
+        message
+    }
+
+    #[test]
+    fn decodes_states() {
+        let (events, _) = decode(&state_change(&[0x03, 0x00, 0x00, 0x00, 0x00]));
+
+        assert!(matches!(
+            events[..],
+            [TimerEvent::State(TimerState::Running)]
+        ));
+    }
+
+    #[test]
+    fn decodes_stopped_times() {
+        let (events, _) = decode(&state_change(&[0x04, 0x00, 0x00, 0x2E, 0x8B]));
+
+        assert!(matches!(
+            events[..],
+            [
+                TimerEvent::State(TimerState::Stopped),
+                TimerEvent::Time(11_915)
+            ]
+        ));
+    }
+
+    #[test]
+    fn acknowledges_every_message() {
+        let (_, ack) = decode(&state_change(&[0x01, 0x00, 0x00, 0x00, 0x00]));
+        assert_eq!(
+            ack.as_deref(),
+            Some(&[0x00, 0x00, 0x00, 0x07, 0x00, 0x10, 0x03][..])
+        );
+
+        let other = [0xFE, 0x0C, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x01, 0x00];
+        let (events, ack) = decode(&other);
+        assert!(events.is_empty());
+        assert_eq!(
+            ack.as_deref(),
+            Some(&[0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x01][..])
+        );
+
+        assert_eq!(decode(&[0xFE, 0x04, 0x00]).1, None);
+    }
+}
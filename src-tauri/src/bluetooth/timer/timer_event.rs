@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bluetooth::timestamp::timestamp;
+
+/// Something a smart timer reported, decoded from its proprietary protocol.
+#[derive(Debug, Clone)]
+pub enum TimerEvent {
+    State(TimerState),
+    /// A solve finished with this time, in milliseconds.
+    Time(u32),
+}
+
+/// A timer event together with the device that reported it.
+#[derive(Debug, Clone)]
+pub struct DeviceTimerEvent {
+    pub device_id: String,
+    /// Represents the time in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub event: TimerEvent,
+}
+
+impl DeviceTimerEvent {
+    pub fn new(device_id: String, event: TimerEvent) -> Self {
+        Self {
+            device_id,
+            timestamp: timestamp(),
+            event,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimerState {
+    /// The timer is about to disconnect, e.g. because it was switched off.
+    Disconnected,
+    /// Hands have been on the pads long enough to start.
+    GetSet,
+    /// Hands were lifted before the timer was ready.
+    HandsOff,
+    Running,
+    Stopped,
+    Idle,
+    HandsOn,
+    /// The stopped time was cleared.
+    Finished,
+}
+
+/// Times stored on a timer, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimerHistory {
+    /// The time currently on the display.
+    pub display_time_ms: u32,
+    /// Earlier times, most recent first.
+    pub previous_times_ms: Vec<u32>,
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use btleplug::{
+    api::{Characteristic, Peripheral as _, WriteType},
+    platform::Peripheral,
+    Error,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tracing::{error, warn};
+use write_queue_message::WriteQueueMessage;
+use write_sequence::{
+    chunks, with_retries, WriteFailure, WriteOperation, WriteOptions, WriteProgress, WriteReport,
+};
+
+use super::error::AppError;
+
+mod write_queue_message;
+pub mod write_sequence;
+
+/// Serializes all write sequences sent to a single device, so that values
+/// reach the peripheral in the order they were queued.
+#[derive(Clone)]
+pub(crate) struct WriteQueue {
+    tx: UnboundedSender<WriteQueueMessage>,
+}
+
+impl WriteQueue {
+    pub fn start(peripheral: Peripheral) -> Self {
+        let actor = WriteQueueActor::new(peripheral);
+        let (tx, rx) = unbounded_channel();
+
+        tokio::spawn(async move {
+            actor.run(rx).await;
+        });
+
+        Self::new(tx)
+    }
+
+    fn new(tx: UnboundedSender<WriteQueueMessage>) -> Self {
+        Self { tx }
+    }
+
+    pub async fn write_sequence(
+        &self,
+        writes: Vec<WriteOperation>,
+        options: WriteOptions,
+        progress: UnboundedSender<WriteProgress>,
+    ) -> Result<WriteReport, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(WriteQueueMessage::WriteSequence {
+                writes,
+                options,
+                progress,
+                result: tx,
+            })
+            .map_err(|_| Error::NotConnected)?;
+
+        // The actor drops pending sequences when the device disconnects.
+        rx.await.map_err(|_| Error::NotConnected)
+    }
+
+    pub async fn stop(&self) {
+        if self.tx.send(WriteQueueMessage::Stop).is_err() {
+            warn!("Write queue already stopped");
+        }
+    }
+}
+
+struct WriteQueueActor {
+    peripheral: Peripheral,
+}
+
+impl WriteQueueActor {
+    fn new(peripheral: Peripheral) -> Self {
+        Self { peripheral }
+    }
+
+    async fn run(&self, mut rx: UnboundedReceiver<WriteQueueMessage>) {
+        while let Some(message) = rx.recv().await {
+            match message {
+                WriteQueueMessage::WriteSequence {
+                    writes,
+                    options,
+                    progress,
+                    result,
+                } => {
+                    let report = self.write_sequence(writes, options, progress).await;
+                    if result.send(report).is_err() {
+                        error!("Failed to send write sequence result");
+                    }
+                }
+                WriteQueueMessage::Stop => break,
+            }
+        }
+    }
+
+    async fn write_sequence(
+        &self,
+        writes: Vec<WriteOperation>,
+        options: WriteOptions,
+        progress: UnboundedSender<WriteProgress>,
+    ) -> WriteReport {
+        let chunk_size = options.chunk_size();
+        let total_chunks = writes
+            .iter()
+            .map(|write| chunks(&write.value, chunk_size).len())
+            .sum();
+        let mut report = WriteReport {
+            written_chunks: 0,
+            total_chunks,
+            failure: None,
+        };
+
+        for (operation, write) in writes.iter().enumerate() {
+            let characteristic = match self.characteristic(write) {
+                Ok(characteristic) => characteristic,
+                Err(err) => {
+                    report.failure = Some(WriteFailure {
+                        operation,
+                        chunk: 0,
+                        attempts: 0,
+                        error: AppError::from(err),
+                    });
+                    return report;
+                }
+            };
+            let write_type = if write.without_response {
+                WriteType::WithoutResponse
+            } else {
+                WriteType::WithResponse
+            };
+
+            for (chunk, data) in chunks(&write.value, chunk_size).into_iter().enumerate() {
+                if report.written_chunks > 0 && options.interval_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(options.interval_ms)).await;
+                }
+
+                if let Err((attempts, err)) = self
+                    .write_chunk(&characteristic, data, write_type, &options)
+                    .await
+                {
+                    report.failure = Some(WriteFailure {
+                        operation,
+                        chunk,
+                        attempts,
+                        error: AppError::from(err),
+                    });
+                    return report;
+                }
+
+                report.written_chunks += 1;
+                // The client may have gone away; the sequence still completes.
+                let _ = progress.send(WriteProgress {
+                    written_chunks: report.written_chunks,
+                    total_chunks,
+                });
+            }
+        }
+
+        report
+    }
+
+    async fn write_chunk(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+        options: &WriteOptions,
+    ) -> Result<(), (u32, Error)> {
+        with_retries(characteristic.uuid, options, || {
+            self.peripheral.write(characteristic, data, write_type)
+        })
+        .await
+    }
+
+    fn characteristic(&self, write: &WriteOperation) -> Result<Characteristic, Error> {
+        self.peripheral
+            .characteristics()
+            .into_iter()
+            .find(|c| c.uuid == write.characteristic_id)
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+}
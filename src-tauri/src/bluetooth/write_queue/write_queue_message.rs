@@ -0,0 +1,13 @@
+use tokio::sync::{mpsc::UnboundedSender, oneshot::Sender};
+
+use super::write_sequence::{WriteOperation, WriteOptions, WriteProgress, WriteReport};
+
+pub(crate) enum WriteQueueMessage {
+    WriteSequence {
+        writes: Vec<WriteOperation>,
+        options: WriteOptions,
+        progress: UnboundedSender<WriteProgress>,
+        result: Sender<WriteReport>,
+    },
+    Stop,
+}
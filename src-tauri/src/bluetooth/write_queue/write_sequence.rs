@@ -0,0 +1,213 @@
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::bluetooth::error::AppError;
+
+/// ATT MTU every BLE link starts with. btleplug does not expose the negotiated
+/// MTU, so unless the client knows better, chunks are sized for this value.
+pub const DEFAULT_ATT_MTU: usize = 23;
+/// Bytes of every ATT write PDU taken up by the opcode and attribute handle.
+const ATT_WRITE_HEADER_LEN: usize = 3;
+
+/// A single value to be written as part of a write sequence.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteOperation {
+    pub characteristic_id: Uuid,
+    pub value: Vec<u8>,
+    #[serde(default)]
+    pub without_response: bool,
+}
+
+/// Chunking, retry and pacing settings for a write sequence.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteOptions {
+    /// ATT MTU used to split values into chunks.
+    pub mtu: usize,
+    /// How many times a chunk is attempted before the sequence is aborted.
+    pub max_attempts: u32,
+    /// Delay between attempts of a failed chunk.
+    pub retry_delay_ms: u64,
+    /// Delay between consecutive chunks, used to pace writes without response.
+    pub interval_ms: u64,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            mtu: DEFAULT_ATT_MTU,
+            max_attempts: 3,
+            retry_delay_ms: 100,
+            interval_ms: 0,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn chunk_size(&self) -> usize {
+        self.mtu.saturating_sub(ATT_WRITE_HEADER_LEN).max(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WriteProgress {
+    pub written_chunks: usize,
+    pub total_chunks: usize,
+}
+
+/// The chunk that could not be written, after all attempts were exhausted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteFailure {
+    /// Index of the operation within the sequence.
+    pub operation: usize,
+    /// Index of the chunk within the operation.
+    pub chunk: usize,
+    pub attempts: u32,
+    pub error: AppError,
+}
+
+/// Outcome of a write sequence. Chunks after a failed one are never written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteReport {
+    pub written_chunks: usize,
+    pub total_chunks: usize,
+    pub failure: Option<WriteFailure>,
+}
+
+/// Splits a value into chunks no larger than `chunk_size`. An empty value
+/// still produces a single, empty chunk so that it is written.
+pub fn chunks(value: &[u8], chunk_size: usize) -> Vec<&[u8]> {
+    if value.is_empty() {
+        vec![value]
+    } else {
+        value.chunks(chunk_size).collect()
+    }
+}
+
+/// Runs `write` until it succeeds or `options.max_attempts` are used up. On
+/// failure, returns the number of attempts made with the last error.
+pub async fn with_retries<F, Fut, E>(
+    characteristic_id: Uuid,
+    options: &WriteOptions,
+    mut write: F,
+) -> Result<(), (u32, E)>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Debug,
+{
+    let max_attempts = options.max_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        match write().await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < max_attempts => {
+                warn!(
+                    "Write attempt {attempt}/{max_attempts} to {characteristic_id} failed: {err:?}"
+                );
+                tokio::time::sleep(Duration::from_millis(options.retry_delay_ms)).await;
+                attempt += 1;
+            }
+            Err(err) => return Err((attempt, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn options(max_attempts: u32) -> WriteOptions {
+        WriteOptions {
+            max_attempts,
+            retry_delay_ms: 0,
+            ..WriteOptions::default()
+        }
+    }
+
+    /// Fails the first `failures` calls, counting all of them.
+    async fn flaky(calls: &Cell<u32>, failures: u32) -> Result<(), &'static str> {
+        calls.set(calls.get() + 1);
+        if calls.get() <= failures {
+            Err("write failed")
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sizes_chunks_by_mtu() {
+        assert_eq!(WriteOptions::default().chunk_size(), 20);
+        assert_eq!(
+            WriteOptions {
+                mtu: 185,
+                ..WriteOptions::default()
+            }
+            .chunk_size(),
+            182
+        );
+    }
+
+    #[test]
+    fn clamps_chunks_of_tiny_mtus_to_one_byte() {
+        for mtu in [0, 2, 3] {
+            let options = WriteOptions {
+                mtu,
+                ..WriteOptions::default()
+            };
+            assert_eq!(options.chunk_size(), 1);
+        }
+    }
+
+    #[test]
+    fn splits_values_into_chunks() {
+        let value: Vec<u8> = (0..45).collect();
+
+        assert_eq!(
+            chunks(&value, 20),
+            [&value[..20], &value[20..40], &value[40..]]
+        );
+        assert_eq!(chunks(&value[..40], 20).len(), 2);
+    }
+
+    #[test]
+    fn writes_empty_values_as_one_empty_chunk() {
+        assert_eq!(chunks(&[], 20), [&[] as &[u8]]);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_writes() {
+        let calls = Cell::new(0);
+
+        let result = with_retries(Uuid::nil(), &options(3), || flaky(&calls, 2)).await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn counts_attempts_until_giving_up() {
+        let calls = Cell::new(0);
+
+        let result = with_retries(Uuid::nil(), &options(3), || flaky(&calls, 5)).await;
+
+        assert_eq!(result, Err((3, "write failed")));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn always_attempts_once() {
+        let calls = Cell::new(0);
+
+        let result = with_retries(Uuid::nil(), &options(0), || flaky(&calls, 5)).await;
+
+        assert_eq!(result, Err((1, "write failed")));
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use futures_util::StreamExt;
 use http::{Response as HttpResponse, Uri};
 use tokio::net::TcpListener;
@@ -29,6 +31,7 @@ impl Server {
         app_status: AppStatus,
         bind_addr: String,
         allow_any_origin: bool,
+        max_scan_time: Duration,
     ) {
         let bluetooth_clone = bluetooth.clone();
         let app_status_clone = app_status.clone();
@@ -39,6 +42,7 @@ impl Server {
                 app_status_clone,
                 bind_addr,
                 allow_any_origin,
+                max_scan_time,
             )
             .await;
         });
@@ -49,6 +53,7 @@ impl Server {
         app_status: AppStatus,
         bind_addr: String,
         allow_any_origin: bool,
+        max_scan_time: Duration,
     ) {
         let listener = create_tcp_listener(&bind_addr).await;
 
@@ -62,6 +67,8 @@ impl Server {
         }
 
         while let Ok((stream, _)) = listener.accept().await {
+            // The callback's error type is set by tungstenite
+            #[allow(clippy::result_large_err)]
             let ws_stream = tokio_tungstenite::accept_hdr_async(
                 stream,
                 |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
@@ -99,7 +106,13 @@ impl Server {
 
             if let Ok(ws_stream) = ws_stream {
                 let (write, read) = ws_stream.split();
-                Connection::start(bluetooth.clone(), app_status.clone(), read, write);
+                Connection::start(
+                    bluetooth.clone(),
+                    app_status.clone(),
+                    max_scan_time,
+                    read,
+                    write,
+                );
             }
         }
     }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use futures_util::stream::{SplitSink, SplitStream};
 use tokio::{net::TcpStream, sync::mpsc::unbounded_channel};
 use tokio_tungstenite::{tungstenite::Message as TungsteniteMessage, WebSocketStream};
@@ -14,16 +16,30 @@ impl Connection {
     pub(crate) fn start(
         bluetooth: Bluetooth,
         app_status: AppStatus,
+        max_scan_time: Duration,
         websocket_read: SplitStream<WebSocketStream<TcpStream>>,
         websocket_write: SplitSink<WebSocketStream<TcpStream>, TungsteniteMessage>,
     ) -> Self {
         let (tx, rx) = unbounded_channel();
         let disconnect_rx = bluetooth.subscribe_to_disconnections();
-        let mut actor =
-            ConnectionActor::new(bluetooth, app_status.clone(), tx.clone(), websocket_write);
+        let services_changed_rx = bluetooth.subscribe_to_service_changes();
+        let battery_rx = bluetooth.subscribe_to_battery_levels();
+        let cube_rx = bluetooth.subscribe_to_cube_events();
+        let timer_rx = bluetooth.subscribe_to_timer_events();
+        let mut actor = ConnectionActor::new(
+            bluetooth,
+            app_status.clone(),
+            max_scan_time,
+            tx.clone(),
+            websocket_write,
+        );
         actor.websocket(websocket_read);
         actor.start_status_listener();
         actor.start_disconnect_listener(disconnect_rx);
+        actor.start_services_changed_listener(services_changed_rx);
+        actor.start_battery_listener(battery_rx);
+        actor.start_cube_listener(cube_rx);
+        actor.start_timer_listener(timer_rx);
 
         tokio::spawn(async move {
             actor.run(rx).await;
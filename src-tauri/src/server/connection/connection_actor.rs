@@ -1,12 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
+use btleplug::Error as BtleError;
 use futures_util::SinkExt;
 use futures_util::{
     stream::{SplitSink, SplitStream},
     StreamExt,
 };
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::oneshot;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    oneshot,
+};
 use tokio::{net::TcpStream, select, sync::mpsc::UnboundedSender};
 use tokio_tungstenite::tungstenite::Error as TungsteniteError;
 use tokio_tungstenite::WebSocketStream;
@@ -14,12 +18,25 @@ use tokio_tungstenite::{self, tungstenite::Message as TungsteniteMessage};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::bluetooth::assigned_numbers;
+use crate::bluetooth::battery::BatteryLevel;
+use crate::bluetooth::cube::{
+    cube_event::{CubeEvent, DeviceCubeEvent},
+    CubeCommand,
+};
+use crate::bluetooth::discovery::{
+    advertisement_stream::{Advertisement, AdvertisementFilter},
+    device_filter::DeviceFilter,
+    signal_strength::DiscoverySort,
+};
 use crate::bluetooth::error::AppError;
+use crate::bluetooth::timer::timer_event::DeviceTimerEvent;
+use crate::bluetooth::write_queue::write_sequence::{WriteOperation, WriteOptions, WriteProgress};
 use crate::server::message::response::Response;
 use crate::{
     app_status::AppStatus,
     bluetooth::{characteristic_value::CharacteristicValue, device_data::DeviceData},
-    server::message::broadcast::Broadcast,
+    server::message::broadcast::{Broadcast, DiscoveryStopReason},
 };
 use crate::{
     bluetooth::notifications::notification_stream::NotificationStream,
@@ -39,15 +56,40 @@ pub(super) enum ConnectionMessage {
     WebsocketMessageReceived(Result<TungsteniteMessage, TungsteniteError>),
     /// An update from Bluetooth discovery
     DevicesDiscovered(Vec<DiscoveredDevice>),
+    /// A single advertisement passing the client's filter
+    AdvertisementReceived(Advertisement),
+    /// An advertisement subscription reached the maximum scan time
+    AdvertisementsStopped,
+    /// A discovery session ended without the client asking for it
+    DiscoveryStopped {
+        session: u64,
+        reason: DiscoveryStopReason,
+    },
     CharacteristicNotification {
         device_id: String,
         characteristic_id: Uuid,
         value: CharacteristicValue,
     },
+    /// Response to a request that was handled outside of the actor loop
+    DeferredResponse { id: String, response: Response },
+    /// Progress of a `WriteSequence` request
+    WriteProgress {
+        request_id: String,
+        device_id: String,
+        progress: WriteProgress,
+    },
     /// Global status update
     StatusChanged(crate::app_status::Status),
     /// A previously-connected device dropped its BT connection unexpectedly
     DeviceDisconnected(String),
+    /// The services of a connected device were rediscovered
+    DeviceServicesChanged(DeviceData),
+    /// A connected device reported a new battery level
+    BatteryLevelChanged(BatteryLevel),
+    /// The driver of a connected smart cube decoded an event
+    CubeEvent(DeviceCubeEvent),
+    /// The driver of a connected smart timer decoded an event
+    TimerEvent(DeviceTimerEvent),
     /// WebSocket connection was closed
     ConnectionClosed,
 }
@@ -57,17 +99,26 @@ pub(super) struct ConnectionActor {
     app_status: AppStatus,
     self_tx: UnboundedSender<ConnectionMessage>,
     websocket_write: SplitSink<WebSocketStream<TcpStream>, TungsteniteMessage>,
+    max_scan_time: Duration,
     discovery_abort: Option<oneshot::Sender<()>>,
+    /// Identifies the running discovery, so that a stale auto-stop doesn't end a
+    /// discovery started after it.
+    discovery_session: u64,
+    discovery_sort: DiscoverySort,
+    advertisements_abort: Option<oneshot::Sender<()>>,
     notification_aborts: HashMap<(String, Uuid), oneshot::Sender<()>>,
-    status_listener_abort: Option<oneshot::Sender<()>>,
-    disconnect_listener_abort: Option<oneshot::Sender<()>>,
+    listener_aborts: Vec<oneshot::Sender<()>>,
     connected_devices: HashMap<String, DeviceData>,
+    /// Gyro decimation set by the client per device, with the number of gyro
+    /// events seen since the last one forwarded.
+    gyro_decimation: HashMap<String, (u32, u32)>,
 }
 
 impl ConnectionActor {
     pub fn new(
         bluetooth: Bluetooth,
         app_status: AppStatus,
+        max_scan_time: Duration,
         self_tx: UnboundedSender<ConnectionMessage>,
         write: SplitSink<WebSocketStream<TcpStream>, TungsteniteMessage>,
     ) -> Self {
@@ -76,11 +127,15 @@ impl ConnectionActor {
             app_status,
             self_tx,
             websocket_write: write,
+            max_scan_time,
             discovery_abort: None,
+            discovery_session: 0,
+            discovery_sort: DiscoverySort::default(),
+            advertisements_abort: None,
             notification_aborts: HashMap::new(),
-            status_listener_abort: None,
-            disconnect_listener_abort: None,
+            listener_aborts: Vec::new(),
             connected_devices: HashMap::new(),
+            gyro_decimation: HashMap::new(),
         }
     }
 
@@ -93,6 +148,13 @@ impl ConnectionActor {
                 ConnectionMessage::DevicesDiscovered(devices) => {
                     self.devices_discovered(devices).await
                 }
+                ConnectionMessage::AdvertisementReceived(advertisement) => {
+                    self.advertisement_received(advertisement).await
+                }
+                ConnectionMessage::AdvertisementsStopped => self.advertisements_stopped().await,
+                ConnectionMessage::DiscoveryStopped { session, reason } => {
+                    self.discovery_stopped(session, reason).await
+                }
                 ConnectionMessage::CharacteristicNotification {
                     device_id,
                     characteristic_id,
@@ -101,10 +163,26 @@ impl ConnectionActor {
                     self.characteristic_notification(device_id, characteristic_id, value)
                         .await
                 }
+                ConnectionMessage::DeferredResponse { id, response } => {
+                    self.deferred_response(id, response).await
+                }
+                ConnectionMessage::WriteProgress {
+                    request_id,
+                    device_id,
+                    progress,
+                } => self.write_progress(request_id, device_id, progress).await,
                 ConnectionMessage::StatusChanged(status) => self.status_changed(status).await,
                 ConnectionMessage::DeviceDisconnected(device_id) => {
                     self.device_disconnected(device_id).await;
                 }
+                ConnectionMessage::DeviceServicesChanged(device) => {
+                    self.device_services_changed(device).await;
+                }
+                ConnectionMessage::BatteryLevelChanged(level) => {
+                    self.battery_level_changed(level).await;
+                }
+                ConnectionMessage::CubeEvent(event) => self.cube_event(event).await,
+                ConnectionMessage::TimerEvent(event) => self.timer_event(event).await,
                 ConnectionMessage::ConnectionClosed => {
                     info!("Connection closed, cleaning up");
                     self.cleanup().await;
@@ -119,6 +197,42 @@ impl ConnectionActor {
             let message: Result<Message, _> = serde_json::from_str(text);
 
             match message {
+                // Scans take seconds, so they are answered later rather than
+                // holding up the connection's other requests and broadcasts.
+                Ok(Message::Request {
+                    request:
+                        Request::Scan {
+                            duration_ms,
+                            filter,
+                        },
+                    id,
+                }) => {
+                    let duration = Duration::from_millis(duration_ms).min(self.max_scan_time);
+                    self.scan(id, duration, filter);
+                    return;
+                }
+                Ok(Message::Request {
+                    request: Request::WaitForDevice { timeout_ms, filter },
+                    id,
+                }) => {
+                    let timeout = Duration::from_millis(timeout_ms).min(self.max_scan_time);
+                    self.wait_for_device(id, timeout, filter);
+                    return;
+                }
+                // Sequences can take long too, and report progress meanwhile
+                Ok(Message::Request {
+                    request:
+                        Request::WriteSequence {
+                            device_id,
+                            writes,
+                            options,
+                        },
+                    id,
+                }) => {
+                    let device_id = self.bluetooth.stable_id(&device_id);
+                    self.write_sequence(id, device_id, writes, options);
+                    return;
+                }
                 Ok(Message::Request { request, id }) => {
                     let response = self.request(request).await;
                     Message::Response { response, id }
@@ -147,7 +261,59 @@ impl ConnectionActor {
         }
     }
 
-    async fn devices_discovered(&mut self, devices: Vec<DiscoveredDevice>) {
+    async fn deferred_response(&mut self, id: String, response: Response) {
+        let serialized = serde_json::to_string(&Message::Response { response, id }).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            error!("Failed to send websocket message: {err:?}");
+        }
+    }
+
+    /// Answers a `Scan` request once the scan is over.
+    fn scan(&self, id: String, duration: Duration, filter: DeviceFilter) {
+        let bluetooth = self.bluetooth.clone();
+        let tx = self.self_tx.clone();
+
+        tokio::spawn(async move {
+            let response = match bluetooth.scan(duration, &filter).await {
+                Ok(devices) => Response::Devices { devices },
+                Err(error) => {
+                    error!("Scan failed: {error:?}");
+                    Response::from(AppError::from(error))
+                }
+            };
+
+            // The connection may have closed in the meantime
+            let _ = tx.send(ConnectionMessage::DeferredResponse { id, response });
+        });
+    }
+
+    /// Answers a `WaitForDevice` request once a matching device shows up.
+    fn wait_for_device(&self, id: String, timeout: Duration, filter: DeviceFilter) {
+        let bluetooth = self.bluetooth.clone();
+        let tx = self.self_tx.clone();
+
+        tokio::spawn(async move {
+            let response = match bluetooth.wait_for_device(timeout, &filter).await {
+                Ok(device) => Response::Device { device },
+                Err(error) => {
+                    warn!("WaitForDevice failed: {error:?}");
+                    Response::from(AppError::from(error))
+                }
+            };
+
+            // The connection may have closed in the meantime
+            let _ = tx.send(ConnectionMessage::DeferredResponse { id, response });
+        });
+    }
+
+    async fn devices_discovered(&mut self, mut devices: Vec<DiscoveredDevice>) {
+        if self.discovery_sort != DiscoverySort::Name {
+            self.discovery_sort.sort(&mut devices);
+        }
         let broadcast = Broadcast::DiscoveredDevices { devices };
 
         let serialized = serde_json::to_string(&broadcast).unwrap();
@@ -160,6 +326,19 @@ impl ConnectionActor {
         }
     }
 
+    async fn advertisement_received(&mut self, advertisement: Advertisement) {
+        let broadcast = Broadcast::Advertisement { advertisement };
+
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send advertisement: {err:?}");
+        }
+    }
+
     async fn characteristic_notification(
         &mut self,
         device_id: String,
@@ -171,6 +350,7 @@ impl ConnectionActor {
             characteristic_id,
             value: value.value,
             timestamp: value.timestamp,
+            source: value.source,
         };
 
         let serialized = serde_json::to_string(&broadcast).unwrap();
@@ -192,68 +372,69 @@ impl ConnectionActor {
         }
     }
 
-    async fn request(&mut self, request: Request) -> Response {
-        match request {
-            Request::StartDiscovery => self.start_discovery().await,
-            Request::StopDiscovery => self.stop_discovery(),
-            Request::Connect { device_id } => {
-                if let Some(device) = self.connected_devices.get(&device_id) {
-                    return Response::Connected {
-                        device: device.clone(),
-                    };
-                }
-
-                let result = self.bluetooth.connect(&device_id).await;
+    async fn request(&mut self, mut request: Request) -> Response {
+        // Platform IDs are accepted as aliases, but devices are tracked by stable ID
+        if let Some(device_id) = request.device_id_mut() {
+            *device_id = self.bluetooth.stable_id(device_id);
+        }
 
-                match result {
-                    Ok(device) => {
-                        self.connected_devices.insert(device_id, device.clone());
-                        Response::Connected { device }
-                    }
-                    Err(error) => {
-                        error!("Connect failed: {error:?}");
-                        Response::from(AppError::from(error))
-                    }
-                }
+        match request {
+            Request::StartDiscovery {
+                duration_ms,
+                idle_timeout_ms,
+                sort,
+            } => {
+                self.start_discovery(
+                    duration_ms.map(Duration::from_millis),
+                    idle_timeout_ms.map(Duration::from_millis),
+                    sort,
+                )
+                .await
+            }
+            Request::StopDiscovery => self.stop_discovery().await,
+            // Answered from websocket_message, once they complete
+            Request::Scan { .. }
+            | Request::WaitForDevice { .. }
+            | Request::WriteSequence { .. } => Response::from(AppError::invalid_state()),
+            Request::SubscribeToAdvertisements { filter } => {
+                self.subscribe_to_advertisements(filter).await
             }
-            Request::Disconnect { device_id } => {
-                if !self.connected_devices.contains_key(&device_id) {
-                    return Response::Ok;
+            Request::UnsubscribeFromAdvertisements => self.unsubscribe_from_advertisements().await,
+            Request::AdapterInfo => self.adapter_info().await,
+            Request::RegisterGattNames { names } => {
+                for (uuid, name) in names {
+                    assigned_numbers::register_vendor_name(uuid, name);
                 }
 
-                match self.bluetooth.disconnect(&device_id).await {
-                    Ok(()) => {
-                        self.connected_devices.remove(&device_id);
+                Response::Ok
+            }
+            Request::Connect { device_id } => self.connect(device_id).await,
+            Request::Disconnect { device_id } => self.disconnect(device_id).await,
+            Request::RefreshServices { device_id } => self.refresh_services(device_id).await,
 
-                        Response::Ok
-                    }
-                    Err(error) => {
-                        error!("Disconnect failed: {error:?}");
-                        Response::from(AppError::from(error))
-                    }
-                }
+            Request::CubeConnect { device_id } => self.cube_connect(device_id).await,
+            Request::CubeRequestState { device_id } => {
+                self.cube_command(device_id, CubeCommand::RequestFacelets)
+                    .await
             }
+            Request::CubeResetState { device_id } => {
+                self.cube_command(device_id, CubeCommand::ResetSolved).await
+            }
+            Request::CubeRequestBattery { device_id } => {
+                self.cube_command(device_id, CubeCommand::RequestBattery)
+                    .await
+            }
+            Request::GetCubeState { device_id } => self.cube_state(device_id).await,
+            Request::TimerHistory { device_id } => self.timer_history(device_id).await,
+            Request::SetGyroDecimation {
+                device_id,
+                decimation,
+            } => self.set_gyro_decimation(device_id, decimation),
 
             Request::ReadCharacteristic {
                 device_id,
                 characteristic_id,
-            } => {
-                let result = self
-                    .bluetooth
-                    .read_characteristic(&device_id, characteristic_id)
-                    .await;
-
-                match result {
-                    Ok(value) => Response::Value {
-                        value: value.value,
-                        timestamp: value.timestamp,
-                    },
-                    Err(error) => {
-                        error!("ReadCharacteristic failed: {error:?}");
-                        Response::from(AppError::from(error))
-                    }
-                }
-            }
+            } => self.read_characteristic(device_id, characteristic_id).await,
             Request::WriteCharacteristic {
                 device_id,
                 characteristic_id,
@@ -283,6 +464,117 @@ impl ConnectionActor {
         }
     }
 
+    async fn connect(&mut self, device_id: String) -> Response {
+        if let Some(device) = self.connected_devices.get(&device_id) {
+            return Response::Connected {
+                device: device.clone(),
+            };
+        }
+
+        match self.bluetooth.connect(&device_id).await {
+            Ok(device) => {
+                self.connected_devices
+                    .insert(device.id.clone(), device.clone());
+                Response::Connected { device }
+            }
+            Err(error) => {
+                error!("Connect failed: {error:?}");
+                Response::from(AppError::from(error))
+            }
+        }
+    }
+
+    async fn disconnect(&mut self, device_id: String) -> Response {
+        if !self.connected_devices.contains_key(&device_id) {
+            return Response::Ok;
+        }
+
+        match self.bluetooth.disconnect(&device_id).await {
+            Ok(()) => {
+                self.connected_devices.remove(&device_id);
+                self.gyro_decimation.remove(&device_id);
+
+                Response::Ok
+            }
+            Err(error) => {
+                error!("Disconnect failed: {error:?}");
+                Response::from(AppError::from(error))
+            }
+        }
+    }
+
+    async fn adapter_info(&self) -> Response {
+        match self.bluetooth.adapter_info().await {
+            Ok(adapter) => Response::AdapterInfo { adapter },
+            Err(error) => {
+                error!("AdapterInfo failed: {error:?}");
+                Response::from(AppError::from(error))
+            }
+        }
+    }
+
+    async fn refresh_services(&self, device_id: String) -> Response {
+        match self.bluetooth.refresh_services(&device_id).await {
+            // The connection's copy is updated by the services changed broadcast
+            Ok(device) => Response::ServicesRefreshed { device },
+            Err(error) => {
+                error!("RefreshServices failed: {error:?}");
+                Response::from(AppError::from(error))
+            }
+        }
+    }
+
+    async fn cube_state(&self, device_id: String) -> Response {
+        match self.bluetooth.cube_state(&device_id).await {
+            Ok(state) => Response::from(state),
+            Err(error) => {
+                error!("GetCubeState failed: {error:?}");
+                Response::from(driver_error(error))
+            }
+        }
+    }
+
+    async fn timer_history(&self, device_id: String) -> Response {
+        match self.bluetooth.timer_history(&device_id).await {
+            Ok(history) => Response::TimerHistory { history },
+            Err(error) => {
+                error!("TimerHistory failed: {error:?}");
+                Response::from(driver_error(error))
+            }
+        }
+    }
+
+    fn set_gyro_decimation(&mut self, device_id: String, decimation: u32) -> Response {
+        if !self.connected_devices.contains_key(&device_id) {
+            error!("SetGyroDecimation called for {device_id}, which is not connected");
+            return Response::from(AppError::from(BtleError::DeviceNotFound));
+        }
+
+        self.gyro_decimation.insert(device_id, (decimation, 0));
+        Response::Ok
+    }
+
+    async fn read_characteristic(
+        &mut self,
+        device_id: String,
+        characteristic_id: Uuid,
+    ) -> Response {
+        let result = self
+            .bluetooth
+            .read_characteristic(&device_id, characteristic_id)
+            .await;
+        match result {
+            Ok(value) => Response::Value {
+                value: value.value,
+                timestamp: value.timestamp,
+            },
+            Err(error) => {
+                error!("ReadCharacteristic failed: {error:?}");
+                Response::from(AppError::from(error))
+            }
+        }
+    }
+
     async fn write_characteristic(
         &mut self,
         device_id: String,
@@ -302,13 +594,184 @@ impl ConnectionActor {
         }
     }
 
-    fn stop_discovery(&mut self) -> Response {
-        if let Some(discovery_abort) = self.discovery_abort.take() {
-            let result = discovery_abort.send(());
+    /// Answers a `WriteSequence` request once the sequence completes,
+    /// broadcasting its progress meanwhile.
+    fn write_sequence(
+        &self,
+        id: String,
+        device_id: String,
+        writes: Vec<WriteOperation>,
+        options: WriteOptions,
+    ) {
+        let bluetooth = self.bluetooth.clone();
+        let tx = self.self_tx.clone();
+
+        tokio::spawn(async move {
+            let (progress_tx, mut progress_rx) = unbounded_channel();
+            let result = bluetooth.write_sequence(&device_id, writes, options, progress_tx);
+            tokio::pin!(result);
+
+            let result = loop {
+                select! {
+                    result = &mut result => break result,
+                    Some(progress) = progress_rx.recv() => {
+                        let _ = tx.send(ConnectionMessage::WriteProgress {
+                            request_id: id.clone(),
+                            device_id: device_id.clone(),
+                            progress,
+                        });
+                    }
+                }
+            };
+
+            let response = match result {
+                Ok(report) => {
+                    if let Some(failure) = &report.failure {
+                        error!(
+                            "WriteSequence failed at operation {} chunk {}: {:?}",
+                            failure.operation, failure.chunk, failure.error
+                        );
+                    }
+                    Response::from(report)
+                }
+                Err(error) => {
+                    error!("WriteSequence failed: {error:?}");
+                    Response::from(AppError::from(error))
+                }
+            };
+
+            // The connection may have closed in the meantime
+            let _ = tx.send(ConnectionMessage::DeferredResponse { id, response });
+        });
+    }
+
+    async fn write_progress(
+        &mut self,
+        request_id: String,
+        device_id: String,
+        progress: WriteProgress,
+    ) {
+        let broadcast = Broadcast::WriteProgress {
+            request_id,
+            device_id,
+            written_chunks: progress.written_chunks,
+            total_chunks: progress.total_chunks,
+        };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send write progress: {err:?}");
+        }
+    }
+
+    async fn subscribe_to_advertisements(&mut self, filter: AdvertisementFilter) -> Response {
+        if self.advertisements_abort.is_some() {
+            error!("SubscribeToAdvertisements called but already subscribed");
+            return Response::from(AppError::invalid_state());
+        }
+
+        match self.bluetooth.subscribe_to_advertisements().await {
+            Ok(mut advertisements) => {
+                let (abort_sender, abort_receiver) = oneshot::channel();
+                let tx = self.self_tx.clone();
+                let max_scan_time = self.max_scan_time;
+
+                tokio::spawn(async move {
+                    use futures_util::FutureExt;
+                    let mut abort = Box::pin(abort_receiver).fuse();
+                    let deadline = tokio::time::sleep(max_scan_time);
+                    tokio::pin!(deadline);
+
+                    let timed_out = loop {
+                        select! {
+                            _ = (&mut abort) => {
+                                break false;
+                            },
+                            _ = &mut deadline => {
+                                break true;
+                            },
+                            advertisement = advertisements.next() => {
+                                let Some(advertisement) = advertisement else {
+                                    break false;
+                                };
+                                if !filter.matches(&advertisement) {
+                                    continue;
+                                }
+                                if let Err(err) = tx.send(ConnectionMessage::AdvertisementReceived(advertisement)) {
+                                    error!("Failed to send advertisement: {err:?}");
+                                    break false;
+                                }
+                            },
+                        }
+                    };
+
+                    if timed_out {
+                        // Closes the abort channel before the actor checks it
+                        drop(abort);
+                        let _ = tx.send(ConnectionMessage::AdvertisementsStopped);
+                    }
+                });
 
-            if let Err(err) = result {
-                error!("Failed to abort discovery: {err:?}");
+                self.advertisements_abort = Some(abort_sender);
+
+                Response::Ok
+            }
+            Err(err) => {
+                error!("SubscribeToAdvertisements failed: {err:?}");
+                Response::from(AppError::from(err))
             }
+        }
+    }
+
+    async fn unsubscribe_from_advertisements(&mut self) -> Response {
+        if let Some(abort) = self.advertisements_abort.take() {
+            let _ = abort.send(());
+            self.bluetooth.unsubscribe_from_discovery().await;
+
+            Response::Ok
+        } else {
+            error!("UnsubscribeFromAdvertisements called but not subscribed");
+            Response::from(AppError::invalid_state())
+        }
+    }
+
+    async fn advertisements_stopped(&mut self) {
+        // A closed abort channel belongs to the subscription that stopped, not
+        // to one the client made since
+        if !self
+            .advertisements_abort
+            .as_ref()
+            .is_some_and(oneshot::Sender::is_closed)
+        {
+            return;
+        }
+        self.advertisements_abort = None;
+        self.bluetooth.unsubscribe_from_discovery().await;
+
+        info!("Advertisement subscription reached the maximum scan time");
+        let broadcast = Broadcast::AdvertisementsStopped {
+            reason: DiscoveryStopReason::MaxScanTime,
+        };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send advertisements stopped: {err:?}");
+        }
+    }
+
+    async fn stop_discovery(&mut self) -> Response {
+        if let Some(discovery_abort) = self.discovery_abort.take() {
+            // The stream task may have just stopped on its own, in which case its
+            // DiscoveryStopped message finds no running discovery and is ignored.
+            let _ = discovery_abort.send(());
+            self.bluetooth.unsubscribe_from_discovery().await;
 
             Response::Ok
         } else {
@@ -318,10 +781,47 @@ impl ConnectionActor {
     }
 
     pub fn start_status_listener(&mut self) {
-        let mut status_rx = self.app_status.subscribe();
+        let status_rx = self.app_status.subscribe();
+        self.start_listener(status_rx, ConnectionMessage::StatusChanged);
+    }
+
+    pub fn start_disconnect_listener(&mut self, disconnect_rx: broadcast::Receiver<String>) {
+        self.start_listener(disconnect_rx, ConnectionMessage::DeviceDisconnected);
+    }
+
+    pub fn start_services_changed_listener(
+        &mut self,
+        services_changed_rx: broadcast::Receiver<DeviceData>,
+    ) {
+        self.start_listener(
+            services_changed_rx,
+            ConnectionMessage::DeviceServicesChanged,
+        );
+    }
+
+    pub fn start_battery_listener(&mut self, battery_rx: broadcast::Receiver<BatteryLevel>) {
+        self.start_listener(battery_rx, ConnectionMessage::BatteryLevelChanged);
+    }
+
+    pub fn start_cube_listener(&mut self, cube_rx: broadcast::Receiver<DeviceCubeEvent>) {
+        self.start_listener(cube_rx, ConnectionMessage::CubeEvent);
+    }
+
+    pub fn start_timer_listener(&mut self, timer_rx: broadcast::Receiver<DeviceTimerEvent>) {
+        self.start_listener(timer_rx, ConnectionMessage::TimerEvent);
+    }
+
+    /// Forwards everything received on a broadcast channel to this actor, until the
+    /// connection is cleaned up or the channel is closed. Values missed while the
+    /// connection lagged behind are skipped.
+    fn start_listener<T, F>(&mut self, mut rx: broadcast::Receiver<T>, to_message: F)
+    where
+        T: Clone + Send + 'static,
+        F: Fn(T) -> ConnectionMessage + Send + 'static,
+    {
         let tx = self.self_tx.clone();
         let (abort_sender, abort_receiver) = oneshot::channel();
-        self.status_listener_abort = Some(abort_sender);
+        self.listener_aborts.push(abort_sender);
 
         tokio::spawn(async move {
             use futures_util::FutureExt;
@@ -332,15 +832,18 @@ impl ConnectionActor {
                     _ = (&mut abort) => {
                         break;
                     },
-                    result = status_rx.recv() => {
+                    result = rx.recv() => {
                         match result {
-                            Ok(status) => {
-                                if let Err(err) = tx.send(ConnectionMessage::StatusChanged(status)) {
-                                    error!("Failed to send status change: {err:?}");
+                            Ok(value) => {
+                                if let Err(err) = tx.send(to_message(value)) {
+                                    error!("Failed to forward broadcast to connection: {err:?}");
                                     break;
                                 }
-                            },
-                            Err(_) => break,
+                            }
+                            Err(RecvError::Lagged(skipped)) => {
+                                warn!("Connection lagged behind, skipped {skipped} broadcasts");
+                            }
+                            Err(RecvError::Closed) => break,
                         }
                     }
                 }
@@ -348,45 +851,153 @@ impl ConnectionActor {
         });
     }
 
-    pub fn start_disconnect_listener(
-        &mut self,
-        mut disconnect_rx: tokio::sync::broadcast::Receiver<String>,
-    ) {
-        let tx = self.self_tx.clone();
-        let (abort_sender, abort_receiver) = oneshot::channel();
-        self.disconnect_listener_abort = Some(abort_sender);
+    async fn device_services_changed(&mut self, device: DeviceData) {
+        let Some(known) = self.connected_devices.get_mut(&device.id) else {
+            return; // device not known to this connection
+        };
+        *known = device.clone();
 
-        tokio::spawn(async move {
-            use futures_util::FutureExt;
-            let mut abort = Box::pin(abort_receiver).fuse();
+        let broadcast = Broadcast::DeviceServicesChanged { device };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send services changed notification to client: {err:?}");
+        }
+    }
 
-            loop {
-                select! {
-                    _ = (&mut abort) => {
-                        break;
-                    },
-                    result = disconnect_rx.recv() => {
-                        match result {
-                            Ok(device_id) => {
-                                if let Err(err) =
-                                    tx.send(ConnectionMessage::DeviceDisconnected(device_id))
-                                {
-                                    error!("Failed to send device disconnected: {err:?}");
-                                    break;
-                                }
-                            }
-                            Err(_) => break,
-                        }
+    async fn battery_level_changed(&mut self, level: BatteryLevel) {
+        let Some(device) = self.connected_devices.get_mut(&level.device_id) else {
+            return; // device not known to this connection
+        };
+        device.battery_level = Some(level.level);
+
+        let broadcast = Broadcast::BatteryLevel {
+            device_id: level.device_id,
+            level: level.level,
+            timestamp: level.timestamp,
+        };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send battery level to client: {err:?}");
+        }
+    }
+
+    async fn cube_event(&mut self, event: DeviceCubeEvent) {
+        if !self.connected_devices.contains_key(&event.device_id) {
+            return; // device not known to this connection
+        }
+        if matches!(event.event, CubeEvent::Gyro(_)) && !self.forward_gyro(&event.device_id) {
+            return;
+        }
+
+        let Ok(broadcast) = Broadcast::try_from(event) else {
+            return;
+        };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send cube event to client: {err:?}");
+        }
+    }
+
+    /// Connects like `connect`, but only to devices with a cube driver, and
+    /// tells what the cube supports.
+    async fn cube_connect(&mut self, device_id: String) -> Response {
+        let known = self.connected_devices.get(&device_id).cloned();
+        let newly_connected = known.is_none();
+        let device = match known {
+            Some(device) => device,
+            None => match self.bluetooth.connect(&device_id).await {
+                Ok(device) => device,
+                Err(error) => {
+                    error!("CubeConnect failed: {error:?}");
+                    return Response::from(AppError::from(error));
+                }
+            },
+        };
+
+        match self.bluetooth.cube_capabilities(&device.id).await {
+            Ok(capabilities) => {
+                self.connected_devices
+                    .insert(device.id.clone(), device.clone());
+                Response::CubeConnected {
+                    device,
+                    capabilities,
+                }
+            }
+            Err(error) => {
+                error!("CubeConnect failed: {error:?}");
+                if newly_connected {
+                    if let Err(err) = self.bluetooth.disconnect(&device.id).await {
+                        warn!(
+                            "Failed to disconnect {} after CubeConnect: {err:?}",
+                            device.id
+                        );
                     }
                 }
+                Response::from(driver_error(error))
             }
-        });
+        }
+    }
+
+    async fn cube_command(&mut self, device_id: String, command: CubeCommand) -> Response {
+        match self.bluetooth.cube_command(&device_id, command).await {
+            Ok(()) => Response::Ok,
+            Err(error) => {
+                error!("{command:?} failed: {error:?}");
+                Response::from(driver_error(error))
+            }
+        }
+    }
+
+    async fn timer_event(&mut self, event: DeviceTimerEvent) {
+        if !self.connected_devices.contains_key(&event.device_id) {
+            return; // device not known to this connection
+        }
+
+        let broadcast = Broadcast::from(event);
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send timer event to client: {err:?}");
+        }
+    }
+
+    /// Counts a gyro event of `device_id` and tells whether it passes decimation.
+    fn forward_gyro(&mut self, device_id: &str) -> bool {
+        let Some((decimation, count)) = self.gyro_decimation.get_mut(device_id) else {
+            return true;
+        };
+        if *decimation == 0 {
+            return false;
+        }
+
+        *count += 1;
+        if *count < *decimation {
+            return false;
+        }
+        *count = 0;
+        true
     }
 
     async fn device_disconnected(&mut self, device_id: String) {
         if self.connected_devices.remove(&device_id).is_none() {
             return; // device not known to this connection
         }
+        self.gyro_decimation.remove(&device_id);
 
         warn!("Device {device_id} disconnected unexpectedly");
 
@@ -435,14 +1046,39 @@ impl ConnectionActor {
         });
     }
 
-    async fn start_discovery(&mut self) -> Response {
+    async fn start_discovery(
+        &mut self,
+        duration: Option<Duration>,
+        idle_timeout: Option<Duration>,
+        sort: DiscoverySort,
+    ) -> Response {
+        if self.discovery_abort.is_some() {
+            error!("StartDiscovery called but discovery is already running");
+            return Response::from(AppError::invalid_state());
+        }
+
         let result = self.bluetooth.subscribe_to_discovery().await;
 
         match result {
             Ok(discovery_stream) => {
                 let (abort_sender, abort_receiver) = oneshot::channel();
 
-                self.devices_discovered_stream(discovery_stream, abort_receiver);
+                let (max_duration, max_reason) = match duration {
+                    Some(duration) if duration < self.max_scan_time => {
+                        (duration, DiscoveryStopReason::DurationElapsed)
+                    }
+                    _ => (self.max_scan_time, DiscoveryStopReason::MaxScanTime),
+                };
+
+                self.discovery_sort = sort;
+                self.discovery_session += 1;
+                self.devices_discovered_stream(
+                    discovery_stream,
+                    abort_receiver,
+                    self.discovery_session,
+                    (max_duration, max_reason),
+                    idle_timeout,
+                );
 
                 self.discovery_abort = Some(abort_sender);
 
@@ -455,10 +1091,16 @@ impl ConnectionActor {
         }
     }
 
+    /// Forwards discovery updates until aborted. The session stops on its own
+    /// once `max_duration` elapses, or when no update arrives within
+    /// `idle_timeout`, and reports why with a `DiscoveryStopped` message.
     pub(crate) fn devices_discovered_stream(
         &self,
         mut discovery_stream: DiscoveryStream,
         abort: oneshot::Receiver<()>,
+        session: u64,
+        (max_duration, max_reason): (Duration, DiscoveryStopReason),
+        idle_timeout: Option<Duration>,
     ) {
         let tx = self.self_tx.clone();
 
@@ -466,25 +1108,61 @@ impl ConnectionActor {
             use futures_util::FutureExt;
             let mut abort = Box::pin(abort).fuse();
 
-            loop {
+            let deadline = tokio::time::sleep(max_duration);
+            tokio::pin!(deadline);
+            let idle = tokio::time::sleep(idle_timeout.unwrap_or(max_duration));
+            tokio::pin!(idle);
+
+            let reason = loop {
                 select! {
                         _ = (&mut abort) => {
-                            break;
+                            return;
+                        },
+                        () = &mut deadline => {
+                            break max_reason;
+                        },
+                        () = &mut idle, if idle_timeout.is_some() => {
+                            break DiscoveryStopReason::IdleTimeout;
                         },
                         devices = discovery_stream.next() => {
                             if let Some(devices) = devices {
+                                if let Some(idle_timeout) = idle_timeout {
+                                    idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                                }
                                 if let Err(err) = tx.send(ConnectionMessage::DevicesDiscovered(devices)) {
                                     error!("Failed to send discovered devices: {err:?}");
                                 }
                             } else {
-                                break;
+                                return;
                             }
                         },
                 }
+            };
+
+            info!("Stopping discovery: {reason:?}");
+            if let Err(err) = tx.send(ConnectionMessage::DiscoveryStopped { session, reason }) {
+                error!("Failed to send discovery stopped: {err:?}");
             }
         });
     }
 
+    async fn discovery_stopped(&mut self, session: u64, reason: DiscoveryStopReason) {
+        if session != self.discovery_session || self.discovery_abort.take().is_none() {
+            return; // already stopped by the client
+        }
+        self.bluetooth.unsubscribe_from_discovery().await;
+
+        let broadcast = Broadcast::DiscoveryStopped { reason };
+        let serialized = serde_json::to_string(&broadcast).unwrap();
+        if let Err(err) = self
+            .websocket_write
+            .send(TungsteniteMessage::Text(serialized))
+            .await
+        {
+            warn!("Failed to send discovery stopped: {err:?}");
+        }
+    }
+
     async fn subscribe_to_characteristic(
         &mut self,
         device_id: String,
@@ -566,7 +1244,7 @@ impl ConnectionActor {
                             if let Err(err) = tx.send(ConnectionMessage::CharacteristicNotification {
                                 device_id: device_id.clone(),
                                 characteristic_id,
-                                value: CharacteristicValue { timestamp, value: value.value },
+                                value: CharacteristicValue { timestamp, ..value },
                             }) {
                                 error!("Failed to send notification: {err:?}");
                             }
@@ -585,7 +1263,7 @@ impl ConnectionActor {
         let connected_devices = std::mem::take(&mut self.connected_devices);
 
         for device_id in connected_devices.keys() {
-            if let Err(err) = self.bluetooth.disconnect(&device_id).await {
+            if let Err(err) = self.bluetooth.disconnect(device_id).await {
                 warn!("Failed to disconnect {device_id} during connection cleanup: {err:?}");
             }
         }
@@ -593,21 +1271,32 @@ impl ConnectionActor {
         // Abort discovery if running
         if let Some(abort) = self.discovery_abort.take() {
             let _ = abort.send(());
+            self.bluetooth.unsubscribe_from_discovery().await;
         }
 
-        // Abort all notification streams
-        for (_, abort) in self.notification_aborts.drain() {
+        // Abort advertisements and let the scan stop
+        if let Some(abort) = self.advertisements_abort.take() {
             let _ = abort.send(());
+            self.bluetooth.unsubscribe_from_discovery().await;
         }
 
-        // Abort status listener
-        if let Some(abort) = self.status_listener_abort.take() {
+        // Abort all notification streams
+        for (_, abort) in self.notification_aborts.drain() {
             let _ = abort.send(());
         }
 
-        // Abort disconnect listener
-        if let Some(abort) = self.disconnect_listener_abort.take() {
+        // Abort the broadcast listeners
+        for abort in self.listener_aborts.drain(..) {
             let _ = abort.send(());
         }
     }
 }
+
+/// Cube and timer drivers fail with `NotSupported` for features the device
+/// lacks, which is no fault of the platform.
+fn driver_error(error: BtleError) -> AppError {
+    match error {
+        BtleError::NotSupported(_) => AppError::not_supported_by_device(),
+        error => AppError::from(error),
+    }
+}
@@ -3,7 +3,18 @@ use std::fmt::{self, Display, Formatter};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{app_status::Status, bluetooth::discovery::discovered_device::DiscoveredDevice};
+use crate::{
+    app_status::Status,
+    bluetooth::{
+        characteristic_value::ValueSource,
+        cube::cube_event::{
+            CubeEvent, CubeMove, DeviceCubeEvent, HardwareInfo, Quaternion, Vector,
+        },
+        device_data::DeviceData,
+        discovery::{advertisement_stream::Advertisement, discovered_device::DiscoveredDevice},
+        timer::timer_event::{DeviceTimerEvent, TimerEvent, TimerState},
+    },
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
@@ -11,27 +22,205 @@ pub enum Broadcast {
     DiscoveredDevices {
         devices: Vec<DiscoveredDevice>,
     },
+    /// Discovery started by `start-discovery` stopped without `stop-discovery`.
+    DiscoveryStopped {
+        reason: DiscoveryStopReason,
+    },
+    Advertisement {
+        #[serde(flatten)]
+        advertisement: Advertisement,
+    },
+    /// The advertisement subscription ended without the client unsubscribing.
+    AdvertisementsStopped {
+        reason: DiscoveryStopReason,
+    },
     CharacteristicValue {
         timestamp: u64,
         device_id: String,
         characteristic_id: Uuid,
         value: Vec<u8>,
+        source: ValueSource,
     },
     Disconnected {
         device_id: String,
     },
+    DeviceServicesChanged {
+        device: DeviceData,
+    },
+    BatteryLevel {
+        device_id: String,
+        level: u8,
+        timestamp: u64,
+    },
+    CubeMove {
+        device_id: String,
+        timestamp: u64,
+        #[serde(flatten)]
+        cube_move: CubeMove,
+    },
+    /// The cube's facelets, after a `cube-request-state` or whenever the cube
+    /// reports them by itself.
+    CubeState {
+        device_id: String,
+        timestamp: u64,
+        serial: Option<u16>,
+        facelets: String,
+    },
+    /// Moves were lost and couldn't be recovered. A `cube-state` follows once
+    /// the cube has reported its facelets.
+    CubeDesync {
+        device_id: String,
+        timestamp: u64,
+        expected_serial: u16,
+        serial: u16,
+    },
+    CubeGyro {
+        device_id: String,
+        timestamp: u64,
+        quaternion: Quaternion,
+        velocity: Option<Vector>,
+    },
+    CubeBattery {
+        device_id: String,
+        timestamp: u64,
+        level: u8,
+    },
+    CubeHardware {
+        device_id: String,
+        timestamp: u64,
+        #[serde(flatten)]
+        hardware: HardwareInfo,
+    },
+    TimerState {
+        device_id: String,
+        timestamp: u64,
+        state: TimerState,
+    },
+    /// A solve finished, follows the `stopped` timer state.
+    TimerTime {
+        device_id: String,
+        timestamp: u64,
+        time_ms: u32,
+    },
+    WriteProgress {
+        request_id: String,
+        device_id: String,
+        written_chunks: usize,
+        total_chunks: usize,
+    },
     StatusChanged {
         status: Status,
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscoveryStopReason {
+    /// The `duration_ms` requested by the client elapsed.
+    DurationElapsed,
+    /// No discovery update arrived within `idle_timeout_ms`.
+    IdleTimeout,
+    /// The server-wide maximum scan time was reached.
+    MaxScanTime,
+}
+
 impl Display for Broadcast {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::DiscoveredDevices { .. } => write!(f, "DiscoveredDevices"),
+            Self::DiscoveryStopped { .. } => write!(f, "DiscoveryStopped"),
+            Self::Advertisement { .. } => write!(f, "Advertisement"),
+            Self::AdvertisementsStopped { .. } => write!(f, "AdvertisementsStopped"),
             Self::CharacteristicValue { .. } => write!(f, "CharacteristicValue"),
             Self::Disconnected { .. } => write!(f, "Disconnected"),
+            Self::DeviceServicesChanged { .. } => write!(f, "DeviceServicesChanged"),
+            Self::BatteryLevel { .. } => write!(f, "BatteryLevel"),
+            Self::CubeMove { .. } => write!(f, "CubeMove"),
+            Self::CubeState { .. } => write!(f, "CubeState"),
+            Self::CubeDesync { .. } => write!(f, "CubeDesync"),
+            Self::CubeGyro { .. } => write!(f, "CubeGyro"),
+            Self::CubeBattery { .. } => write!(f, "CubeBattery"),
+            Self::CubeHardware { .. } => write!(f, "CubeHardware"),
+            Self::TimerState { .. } => write!(f, "TimerState"),
+            Self::TimerTime { .. } => write!(f, "TimerTime"),
+            Self::WriteProgress { .. } => write!(f, "WriteProgress"),
             Self::StatusChanged { .. } => write!(f, "StatusChanged"),
         }
     }
 }
+
+/// Fails for the events that are only meant for the server.
+impl TryFrom<DeviceCubeEvent> for Broadcast {
+    type Error = CubeEvent;
+
+    fn try_from(event: DeviceCubeEvent) -> Result<Self, Self::Error> {
+        let DeviceCubeEvent {
+            device_id,
+            timestamp,
+            event,
+        } = event;
+
+        Ok(match event {
+            CubeEvent::Move(cube_move) => Self::CubeMove {
+                device_id,
+                timestamp,
+                cube_move,
+            },
+            CubeEvent::Facelets { serial, facelets } => Self::CubeState {
+                device_id,
+                timestamp,
+                serial,
+                facelets,
+            },
+            CubeEvent::Gyro(gyro) => Self::CubeGyro {
+                device_id,
+                timestamp,
+                quaternion: gyro.quaternion,
+                velocity: gyro.velocity,
+            },
+            CubeEvent::Battery(level) => Self::CubeBattery {
+                device_id,
+                timestamp,
+                level,
+            },
+            CubeEvent::Hardware(hardware) => Self::CubeHardware {
+                device_id,
+                timestamp,
+                hardware,
+            },
+            CubeEvent::Desync {
+                expected_serial,
+                serial,
+            } => Self::CubeDesync {
+                device_id,
+                timestamp,
+                expected_serial,
+                serial,
+            },
+            event @ CubeEvent::MoveHistory(_) => return Err(event),
+        })
+    }
+}
+
+impl From<DeviceTimerEvent> for Broadcast {
+    fn from(event: DeviceTimerEvent) -> Self {
+        let DeviceTimerEvent {
+            device_id,
+            timestamp,
+            event,
+        } = event;
+
+        match event {
+            TimerEvent::State(state) => Self::TimerState {
+                device_id,
+                timestamp,
+                state,
+            },
+            TimerEvent::Time(time_ms) => Self::TimerTime {
+                device_id,
+                timestamp,
+                time_ms,
+            },
+        }
+    }
+}
@@ -1,17 +1,68 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::bluetooth::{
+    discovery::{
+        advertisement_stream::AdvertisementFilter, device_filter::DeviceFilter,
+        signal_strength::DiscoverySort,
+    },
+    write_queue::write_sequence::{WriteOperation, WriteOptions},
+};
+
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Request {
-    StartDiscovery,
+    /// Starts discovery, which stops on its own after `duration_ms`, after no
+    /// discovery update for `idle_timeout_ms`, or at the server's maximum scan time.
+    StartDiscovery {
+        #[serde(default)]
+        duration_ms: Option<u64>,
+        #[serde(default)]
+        idle_timeout_ms: Option<u64>,
+        /// Order of the `discovered-devices` lists, by name unless set.
+        #[serde(default)]
+        sort: DiscoverySort,
+    },
     StopDiscovery,
+    /// Runs discovery for a fixed time, at most the server's maximum scan time,
+    /// and responds with the devices found.
+    Scan {
+        duration_ms: u64,
+        #[serde(default)]
+        filter: DeviceFilter,
+    },
+    /// Runs discovery until a matching device appears, for at most the
+    /// server's maximum scan time.
+    WaitForDevice {
+        timeout_ms: u64,
+        #[serde(default)]
+        filter: DeviceFilter,
+    },
+    /// Streams every received advertisement as an `advertisement` broadcast,
+    /// until the server's maximum scan time is reached, which is announced by
+    /// an `advertisements-stopped` broadcast.
+    SubscribeToAdvertisements {
+        #[serde(default)]
+        filter: AdvertisementFilter,
+    },
+    UnsubscribeFromAdvertisements,
+    AdapterInfo,
+    /// Names vendor-specific service, characteristic and descriptor UUIDs in
+    /// subsequently returned `DeviceData`.
+    RegisterGattNames {
+        names: HashMap<Uuid, String>,
+    },
     Connect {
         device_id: String,
     },
     Disconnect {
         device_id: String,
     },
+    RefreshServices {
+        device_id: String,
+    },
     ReadCharacteristic {
         device_id: String,
         characteristic_id: Uuid,
@@ -21,6 +72,12 @@ pub enum Request {
         characteristic_id: Uuid,
         value: Vec<u8>,
     },
+    WriteSequence {
+        device_id: String,
+        writes: Vec<WriteOperation>,
+        #[serde(default)]
+        options: WriteOptions,
+    },
     SubscribeToCharacteristic {
         device_id: String,
         characteristic_id: Uuid,
@@ -29,5 +86,68 @@ pub enum Request {
         device_id: String,
         characteristic_id: Uuid,
     },
+    /// Connects to a smart cube, failing for devices without a cube driver.
+    CubeConnect {
+        device_id: String,
+    },
+    /// Answered with a `cube-state` broadcast.
+    CubeRequestState {
+        device_id: String,
+    },
+    /// Makes the cube consider its current state solved.
+    CubeResetState {
+        device_id: String,
+    },
+    /// Answered with a `cube-battery` broadcast.
+    CubeRequestBattery {
+        device_id: String,
+    },
+    /// Answered with the state the server tracks from the cube's moves and
+    /// facelets, without asking the cube.
+    GetCubeState {
+        device_id: String,
+    },
+    /// Forwards only every `decimation`th gyro event of a connected smart cube
+    /// to this connection. 1 forwards all of them, 0 none.
+    SetGyroDecimation {
+        device_id: String,
+        decimation: u32,
+    },
+    /// Reads the times stored on a connected smart timer.
+    TimerHistory {
+        device_id: String,
+    },
     Status,
 }
+
+impl Request {
+    /// The device the request targets, if any.
+    pub fn device_id_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Self::Connect { device_id }
+            | Self::Disconnect { device_id }
+            | Self::RefreshServices { device_id }
+            | Self::ReadCharacteristic { device_id, .. }
+            | Self::WriteCharacteristic { device_id, .. }
+            | Self::WriteSequence { device_id, .. }
+            | Self::SubscribeToCharacteristic { device_id, .. }
+            | Self::UnsubscribeFromCharacteristic { device_id, .. }
+            | Self::CubeConnect { device_id }
+            | Self::CubeRequestState { device_id }
+            | Self::CubeResetState { device_id }
+            | Self::CubeRequestBattery { device_id }
+            | Self::GetCubeState { device_id }
+            | Self::SetGyroDecimation { device_id, .. }
+            | Self::TimerHistory { device_id } => Some(device_id),
+            Self::StartDiscovery { .. }
+            | Self::StopDiscovery
+            | Self::Scan { .. }
+            | Self::WaitForDevice { .. }
+            | Self::SubscribeToAdvertisements { .. }
+            | Self::UnsubscribeFromAdvertisements
+            | Self::AdapterInfo
+            | Self::RegisterGattNames { .. }
+            | Self::Status => None,
+        }
+    }
+}
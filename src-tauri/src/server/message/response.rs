@@ -3,12 +3,17 @@ use serde::{Deserialize, Serialize};
 use crate::{
     app_status::Status,
     bluetooth::{
+        adapter_info::AdapterInfo,
+        cube::{cube_state::CubeState, facelets::CubieState, CubeCapabilities},
         device_data::DeviceData,
+        discovery::discovered_device::DiscoveredDevice,
         error::{AppError, ErrorCategory, ErrorCode},
+        timer::timer_event::TimerHistory,
+        write_queue::write_sequence::{WriteFailure, WriteReport},
     },
 };
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "result", rename_all = "kebab-case")]
 pub enum Response {
     Ok,
@@ -23,9 +28,40 @@ pub enum Response {
     Status {
         status: Status,
     },
+    AdapterInfo {
+        adapter: AdapterInfo,
+    },
     Connected {
         device: DeviceData,
     },
+    Devices {
+        devices: Vec<DiscoveredDevice>,
+    },
+    Device {
+        device: DiscoveredDevice,
+    },
+    ServicesRefreshed {
+        device: DeviceData,
+    },
+    CubeConnected {
+        device: DeviceData,
+        capabilities: CubeCapabilities,
+    },
+    WriteSequence {
+        written_chunks: usize,
+        total_chunks: usize,
+        failure: Option<WriteFailure>,
+    },
+    TimerHistory {
+        #[serde(flatten)]
+        history: TimerHistory,
+    },
+    /// Both `null` until the cube has reported its facelets.
+    CubeState {
+        /// Kociemba facelet string.
+        facelets: Option<String>,
+        cubies: Option<CubieState>,
+    },
 }
 
 impl From<AppError> for Response {
@@ -36,3 +72,22 @@ impl From<AppError> for Response {
         }
     }
 }
+
+impl From<Option<CubeState>> for Response {
+    fn from(state: Option<CubeState>) -> Self {
+        Self::CubeState {
+            facelets: state.as_ref().map(CubeState::facelets),
+            cubies: state.as_ref().map(|state| state.cubies().clone()),
+        }
+    }
+}
+
+impl From<WriteReport> for Response {
+    fn from(report: WriteReport) -> Self {
+        Self::WriteSequence {
+            written_chunks: report.written_chunks,
+            total_chunks: report.total_chunks,
+            failure: report.failure,
+        }
+    }
+}
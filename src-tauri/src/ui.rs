@@ -10,12 +10,38 @@ use tauri_plugin_autostart::MacosLauncher;
 use tauri_plugin_cli::CliExt as _;
 use tauri_plugin_opener::open_url;
 use tauri_plugin_updater::UpdaterExt as _;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 use crate::app_status::{AppStatus, Status};
 use crate::bluetooth::{device_data::DeviceData, Bluetooth};
 use crate::server::Server;
 
+/// Longest a discovery started by a WebSocket client may run, unless overridden
+/// with `--max-scan-time`.
+const DEFAULT_MAX_SCAN_TIME_SECS: u64 = 300;
+/// Shorter limits would stop discoveries before they find anything.
+const MIN_MAX_SCAN_TIME_SECS: u64 = 10;
+
+/// Parses `--max-scan-time`, falling back to the default for values that
+/// aren't a number of seconds and raising values below the minimum.
+fn max_scan_time(value: Option<&str>) -> u64 {
+    let Some(value) = value else {
+        return DEFAULT_MAX_SCAN_TIME_SECS;
+    };
+
+    match value.parse() {
+        Ok(seconds) if seconds < MIN_MAX_SCAN_TIME_SECS => {
+            warn!("--max-scan-time {seconds} is too short, using {MIN_MAX_SCAN_TIME_SECS} seconds");
+            MIN_MAX_SCAN_TIME_SECS
+        }
+        Ok(seconds) => seconds,
+        Err(_) => {
+            warn!("Invalid --max-scan-time {value:?}, using {DEFAULT_MAX_SCAN_TIME_SECS} seconds");
+            DEFAULT_MAX_SCAN_TIME_SECS
+        }
+    }
+}
+
 struct Context {
     bluetooth: Bluetooth,
     status: AppStatus,
@@ -126,11 +152,19 @@ pub fn build_tauri(bluetooth: Bluetooth, status: AppStatus) -> tauri::Builder<Wr
                     .and_then(|value| value.value.as_bool())
                     .unwrap_or(false);
 
+                let max_scan_time = max_scan_time(
+                    matches
+                        .args
+                        .get("max-scan-time")
+                        .and_then(|value| value.value.as_str()),
+                );
+
                 Server::start(
                     bluetooth_for_setup.clone(),
                     status_for_setup.clone(),
                     bind_addr,
                     allow_any_origin,
+                    Duration::from_secs(max_scan_time),
                 );
             }
 
@@ -11,7 +11,7 @@ export type ErrorCategory = 'system' | 'connectivity' | 'device' | 'internal';
 export type SystemErrorCode =
   | 'permission_denied'  // OS refused the Bluetooth permission
   | 'no_adapter'         // No Bluetooth adapter detected on this system
-  | 'not_supported'      // Operation not supported on this platform
+  | 'not_supported'      // Operation not supported on this platform or by the device
   | 'runtime_error';     // Low-level platform runtime error (incl. OS BT-stack errors)
 
 export type ConnectivityErrorCode =
@@ -1,20 +1,50 @@
+export interface DescriptorData {
+  uuid: string;
+  name?: string;
+}
+
 export interface CharacteristicData {
   uuid: string;
+  name?: string;
   read: boolean;
   write: boolean;
   write_without_response: boolean;
   notify: boolean;
+  indicate: boolean;
+  descriptors: DescriptorData[];
 }
 
 export interface ServiceData {
   uuid: string;
+  name?: string;
   characteristics: CharacteristicData[];
 }
 
+export interface DeviceKind {
+  brand: string;
+  family?: string;
+  protocol?: string;
+  category: 'cube' | 'timer';
+}
+
+export interface DeviceInfo {
+  manufacturer_name?: string;
+  model_number?: string;
+  serial_number?: string;
+  hardware_revision?: string;
+  firmware_revision?: string;
+  software_revision?: string;
+}
+
 export interface DeviceData {
   id: string;
+  platform_id: string;
   name: string;
   address: string;
+  derived_address?: string;
   manufacturer_data: Record<string, any>;
+  device_kind?: DeviceKind;
   services: ServiceData[];
+  device_info?: DeviceInfo;
+  battery_level?: number;
 }
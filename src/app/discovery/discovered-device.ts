@@ -1,9 +1,18 @@
+import { DeviceKind } from '../device-details/device-data';
+
 export type ManufacturerData = { [index: number]: number[] };
 
 export interface DiscoveredDevice {
   id: string,
+  platform_id: string,
   name?: string,
   address?: string,
+  derived_address?: string,
   signal_strength?: number,
+  smoothed_signal_strength?: number,
+  proximity?: 'immediate' | 'near' | 'far',
   manufacturer_data?: ManufacturerData,
+  manufacturer_names: { [index: number]: string },
+  services: string[],
+  device_kind?: DeviceKind,
 }